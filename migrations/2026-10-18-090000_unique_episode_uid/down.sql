@@ -0,0 +1 @@
+DROP INDEX episodes_pod_id_uid;
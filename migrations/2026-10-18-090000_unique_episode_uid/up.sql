@@ -0,0 +1,4 @@
+DELETE FROM episodes
+WHERE id NOT IN (SELECT MIN(id) FROM episodes GROUP BY pod_id, uid);
+
+CREATE UNIQUE INDEX episodes_pod_id_uid ON episodes (pod_id, uid);
@@ -0,0 +1 @@
+ALTER TABLE episodes ADD COLUMN audio_changed BOOLEAN NOT NULL DEFAULT FALSE;
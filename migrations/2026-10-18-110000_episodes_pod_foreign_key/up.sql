@@ -0,0 +1,31 @@
+DELETE FROM episodes WHERE pod_id NOT IN (SELECT id FROM pods);
+
+CREATE TABLE episodes_new (
+    id INTEGER NOT NULL PRIMARY KEY,
+    uid VARCHAR NOT NULL,
+    pod_id INTEGER NOT NULL REFERENCES pods (id) ON DELETE CASCADE,
+    title VARCHAR NOT NULL,
+    url VARCHAR NOT NULL,
+    audio_url VARCHAR NOT NULL,
+    description VARCHAR NOT NULL,
+    audio_filepath VARCHAR,
+    downloaded BOOLEAN NOT NULL DEFAULT FALSE,
+    played BOOLEAN NOT NULL DEFAULT FALSE,
+    timestamp REAL NOT NULL DEFAULT 0.0,
+    pub_timestamp INTEGER NOT NULL,
+    duration INTEGER,
+    audio_changed BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO episodes_new (id, uid, pod_id, title, url, audio_url, description, audio_filepath,
+    downloaded, played, timestamp, pub_timestamp, duration, audio_changed)
+SELECT id, uid, pod_id, title, url, audio_url, description, audio_filepath,
+    downloaded, played, timestamp, pub_timestamp, duration, audio_changed
+FROM episodes;
+
+DROP TABLE episodes;
+ALTER TABLE episodes_new RENAME TO episodes;
+
+CREATE UNIQUE INDEX episodes_pod_id_uid ON episodes (pod_id, uid);
+CREATE INDEX episodes_pod_id_pub_timestamp ON episodes (pod_id, pub_timestamp);
+CREATE INDEX episodes_pub_timestamp ON episodes (pub_timestamp);
@@ -0,0 +1,4 @@
+DROP TRIGGER episodes_fts_update;
+DROP TRIGGER episodes_fts_delete;
+DROP TRIGGER episodes_fts_insert;
+DROP TABLE episodes_fts;
@@ -0,0 +1,28 @@
+CREATE VIRTUAL TABLE episodes_fts USING fts5(
+    title,
+    description,
+    content = 'episodes',
+    content_rowid = 'id'
+);
+
+INSERT INTO episodes_fts (rowid, title, description)
+SELECT id, title, description FROM episodes;
+
+CREATE TRIGGER episodes_fts_insert AFTER INSERT ON episodes BEGIN
+    INSERT INTO episodes_fts (rowid, title, description)
+    VALUES (new.id, new.title, new.description);
+END;
+
+CREATE TRIGGER episodes_fts_delete AFTER DELETE ON episodes BEGIN
+    INSERT INTO episodes_fts (episodes_fts, rowid, title, description)
+    VALUES ('delete', old.id, old.title, old.description);
+END;
+
+-- A refresh sets title and description of every episode, only reindex real changes
+CREATE TRIGGER episodes_fts_update AFTER UPDATE OF title, description ON episodes
+WHEN old.title IS NOT new.title OR old.description IS NOT new.description BEGIN
+    INSERT INTO episodes_fts (episodes_fts, rowid, title, description)
+    VALUES ('delete', old.id, old.title, old.description);
+    INSERT INTO episodes_fts (rowid, title, description)
+    VALUES (new.id, new.title, new.description);
+END;
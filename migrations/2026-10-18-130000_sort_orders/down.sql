@@ -0,0 +1,4 @@
+DROP TABLE settings;
+ALTER TABLE pods DROP COLUMN episode_sort;
+ALTER TABLE episodes DROP COLUMN episode_number;
+ALTER TABLE episodes DROP COLUMN season;
@@ -0,0 +1,8 @@
+ALTER TABLE episodes ADD COLUMN season INTEGER;
+ALTER TABLE episodes ADD COLUMN episode_number INTEGER;
+ALTER TABLE pods ADD COLUMN episode_sort VARCHAR NOT NULL DEFAULT 'date_desc';
+
+CREATE TABLE settings (
+    key VARCHAR NOT NULL PRIMARY KEY,
+    value VARCHAR NOT NULL
+);
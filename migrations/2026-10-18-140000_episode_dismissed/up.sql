@@ -0,0 +1 @@
+ALTER TABLE episodes ADD COLUMN dismissed BOOLEAN NOT NULL DEFAULT FALSE;
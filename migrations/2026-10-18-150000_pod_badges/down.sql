@@ -0,0 +1,2 @@
+ALTER TABLE pods DROP COLUMN last_refreshed;
+ALTER TABLE episodes DROP COLUMN is_new;
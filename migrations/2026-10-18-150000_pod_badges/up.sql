@@ -0,0 +1,2 @@
+ALTER TABLE episodes ADD COLUMN is_new BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE pods ADD COLUMN last_refreshed INTEGER;
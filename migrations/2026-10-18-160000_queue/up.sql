@@ -0,0 +1,5 @@
+CREATE TABLE queue (
+    episode_id INTEGER NOT NULL PRIMARY KEY REFERENCES episodes (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL
+);
+CREATE INDEX queue_position ON queue (position);
@@ -0,0 +1 @@
+ALTER TABLE pods ADD COLUMN continuous_play BOOLEAN NOT NULL DEFAULT FALSE;
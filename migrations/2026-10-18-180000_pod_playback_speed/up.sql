@@ -0,0 +1 @@
+ALTER TABLE pods ADD COLUMN playback_speed REAL NOT NULL DEFAULT 1.0;
@@ -0,0 +1,2 @@
+ALTER TABLE episodes DROP COLUMN time_saved;
+ALTER TABLE episodes DROP COLUMN silences;
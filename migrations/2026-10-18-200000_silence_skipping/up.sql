@@ -0,0 +1,2 @@
+ALTER TABLE episodes ADD COLUMN silences TEXT;
+ALTER TABLE episodes ADD COLUMN time_saved REAL NOT NULL DEFAULT 0;
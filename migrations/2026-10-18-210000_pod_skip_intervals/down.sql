@@ -0,0 +1,2 @@
+ALTER TABLE pods DROP COLUMN skip_back;
+ALTER TABLE pods DROP COLUMN skip_forward;
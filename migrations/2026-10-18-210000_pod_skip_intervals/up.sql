@@ -0,0 +1,2 @@
+ALTER TABLE pods ADD COLUMN skip_forward INTEGER;
+ALTER TABLE pods ADD COLUMN skip_back INTEGER;
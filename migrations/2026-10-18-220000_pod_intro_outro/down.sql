@@ -0,0 +1,2 @@
+ALTER TABLE pods DROP COLUMN skip_outro;
+ALTER TABLE pods DROP COLUMN skip_intro;
@@ -0,0 +1,2 @@
+ALTER TABLE pods ADD COLUMN skip_intro INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE pods ADD COLUMN skip_outro INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,271 @@
+use crate::loudness::{gain_for, Meter};
+
+use serde::{Deserialize, Serialize};
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use std::fs::File;
+use std::path::Path;
+
+/// Quieter than this counts as silence, well below the pauses of a noisy recording
+const SILENCE_DB: f64 = -45.0;
+/// Pauses shorter than this are left alone, they belong to the rhythm of speech
+const MIN_SILENCE_SECS: f32 = 1.0;
+
+/// A stretch of silence in an episode, in seconds from the start
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Silence {
+    pub start: f32,
+    pub end: f32,
+}
+
+pub struct AudioAnalysis {
+    pub loudness_gain: Option<f32>,
+    pub silences: Vec<Silence>,
+}
+
+/// Decodes a whole file once to measure its loudness and find long silences.
+/// Returns `None` when the file can't be decoded.
+pub fn analyse(path: &Path) -> Option<AudioAnalysis> {
+    let mut hint = Hint::new();
+    if let Some(extension_str) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension_str);
+    }
+    let source = Box::new(File::open(path).ok()?);
+    let mss = MediaSourceStream::new(source, Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?
+        .format;
+    let track = format.default_track()?;
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+
+    let mut meter: Option<Meter> = None;
+    let mut silence_finder = SilenceFinder::new(sample_rate);
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) => break,
+            Err(_) => return None,
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt frame here and there shouldn't spoil the analysis
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(_) => return None,
+        };
+        let channels = decoded.spec().channels.count();
+        let buffer = samples
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        if buffer.capacity() < decoded.capacity() * channels {
+            *buffer = SampleBuffer::new(decoded.capacity() as u64, *decoded.spec());
+        }
+        buffer.copy_interleaved_ref(decoded);
+        let meter = meter.get_or_insert_with(|| Meter::new(sample_rate, channels));
+        for frame in buffer.samples().chunks_exact(channels) {
+            meter.add_frame(frame);
+            silence_finder.add_frame(frame);
+        }
+    }
+    Some(AudioAnalysis {
+        loudness_gain: meter?.integrated().map(gain_for),
+        silences: silence_finder.finish(),
+    })
+}
+
+/// Looks at the level of 100ms steps and collects runs of quiet ones
+struct SilenceFinder {
+    step_len: usize,
+    step_position: usize,
+    step_sum: f64,
+    step_samples: usize,
+    steps_done: usize,
+    threshold: f64,
+    silent_since: Option<usize>,
+    silences: Vec<Silence>,
+}
+
+impl SilenceFinder {
+    fn new(sample_rate: u32) -> SilenceFinder {
+        SilenceFinder {
+            step_len: (sample_rate as usize / 10).max(1),
+            step_position: 0,
+            step_sum: 0.0,
+            step_samples: 0,
+            steps_done: 0,
+            threshold: 10f64.powf(SILENCE_DB / 10.0),
+            silent_since: None,
+            silences: Vec::new(),
+        }
+    }
+
+    fn add_frame(&mut self, frame: &[f32]) {
+        for &sample in frame {
+            self.step_sum += (sample as f64) * (sample as f64);
+        }
+        self.step_samples += frame.len();
+        self.step_position += 1;
+        if self.step_position < self.step_len {
+            return;
+        }
+        let silent = self.step_sum / self.step_samples as f64 <= self.threshold;
+        match (silent, self.silent_since) {
+            (true, None) => self.silent_since = Some(self.steps_done),
+            (false, Some(since)) => {
+                self.push(since, self.steps_done);
+                self.silent_since = None;
+            }
+            _ => {}
+        }
+        self.steps_done += 1;
+        self.step_position = 0;
+        self.step_sum = 0.0;
+        self.step_samples = 0;
+    }
+
+    fn push(&mut self, from_step: usize, to_step: usize) {
+        let silence = Silence {
+            start: from_step as f32 / 10.0,
+            end: to_step as f32 / 10.0,
+        };
+        if silence.end - silence.start >= MIN_SILENCE_SECS {
+            self.silences.push(silence);
+        }
+    }
+
+    fn finish(mut self) -> Vec<Silence> {
+        if let Some(since) = self.silent_since.take() {
+            self.push(since, self.steps_done);
+        }
+        self.silences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    /// Feeds `secs` of a square wave at `dbfs` to every channel
+    fn add_tone(finder: &mut SilenceFinder, channels: usize, dbfs: f64, secs: f64) {
+        let amplitude = 10f64.powf(dbfs / 20.0) as f32;
+        for n in 0..(secs * RATE as f64).round() as usize {
+            let sample = if n % 2 == 0 { amplitude } else { -amplitude };
+            finder.add_frame(&vec![sample; channels]);
+        }
+    }
+
+    fn add_silence(finder: &mut SilenceFinder, secs: f64) {
+        add_tone(finder, 1, -200.0, secs);
+    }
+
+    #[test]
+    fn finds_nothing_in_steady_audio() {
+        let mut finder = SilenceFinder::new(RATE);
+        add_tone(&mut finder, 1, -20.0, 5.0);
+        assert_eq!(finder.finish(), vec![]);
+    }
+
+    #[test]
+    fn finds_a_pause_between_audio() {
+        let mut finder = SilenceFinder::new(RATE);
+        add_tone(&mut finder, 1, -20.0, 2.0);
+        add_silence(&mut finder, 1.5);
+        add_tone(&mut finder, 1, -20.0, 1.0);
+        assert_eq!(
+            finder.finish(),
+            vec![Silence {
+                start: 2.0,
+                end: 3.5
+            }]
+        );
+    }
+
+    #[test]
+    fn leaves_short_pauses_alone() {
+        let mut finder = SilenceFinder::new(RATE);
+        add_tone(&mut finder, 1, -20.0, 1.0);
+        add_silence(&mut finder, 0.9);
+        add_tone(&mut finder, 1, -20.0, 1.0);
+        add_silence(&mut finder, 1.0);
+        add_tone(&mut finder, 1, -20.0, 1.0);
+        assert_eq!(
+            finder.finish(),
+            vec![Silence {
+                start: 2.9,
+                end: 3.9
+            }]
+        );
+    }
+
+    #[test]
+    fn counts_quiet_audio_below_the_threshold_as_silence() {
+        let mut finder = SilenceFinder::new(RATE);
+        add_tone(&mut finder, 2, -20.0, 1.0);
+        add_tone(&mut finder, 2, -50.0, 2.0);
+        add_tone(&mut finder, 2, -40.0, 2.0);
+        add_tone(&mut finder, 2, -20.0, 1.0);
+        assert_eq!(
+            finder.finish(),
+            vec![Silence {
+                start: 1.0,
+                end: 3.0
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_silence_at_the_start_and_the_end() {
+        let mut finder = SilenceFinder::new(RATE);
+        add_silence(&mut finder, 1.2);
+        add_tone(&mut finder, 1, -20.0, 2.0);
+        add_silence(&mut finder, 3.0);
+        assert_eq!(
+            finder.finish(),
+            vec![
+                Silence {
+                    start: 0.0,
+                    end: 1.2
+                },
+                Silence {
+                    start: 3.2,
+                    end: 6.2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_a_trailing_partial_step() {
+        let mut finder = SilenceFinder::new(RATE);
+        add_tone(&mut finder, 1, -20.0, 1.0);
+        add_silence(&mut finder, 1.05);
+        assert_eq!(
+            finder.finish(),
+            vec![Silence {
+                start: 1.0,
+                end: 2.0
+            }]
+        );
+    }
+}
@@ -1,14 +1,42 @@
 extern crate rss;
 extern crate tui;
 
-use crate::db::models::{Episode, Pod};
-use crate::db::{create_pod, delete_pod, establish_connection, get_episode, get_episodes_for_pod, get_pod, get_pods, set_timestamp_on_episode};
+use crate::config::SkipIntervals;
+use crate::db::models::{Episode, EpisodeSort, Pod, PodSort, PodStats};
+use crate::db::{
+    add_time_saved, clear_new_episodes, create_pod, delete_pod, dismiss_episode, enqueue_episode,
+    get_episode, get_episodes_for_pod, get_inbox, get_next_episode, get_pod, get_pod_stats,
+    get_pods, get_queue, get_setting, get_total_time_saved, mark_older_as_played, move_in_queue,
+    remove_from_queue, search_episodes, set_continuous_play, set_episode_sort, set_intro_outro,
+    set_playback_speed, set_played_on_episode, set_setting, set_skip_intervals,
+    set_timestamp_on_episode,
+};
+use crate::player::{find_timestamps, parse_position, Timestamp};
+use crate::position::PositionTracker;
 use crate::{network::IoEvent, player::Player};
+use diesel::SqliteConnection;
 use kira::sound::PlaybackState;
 use std::fs;
 use tui::widgets::ListState;
 
+use std::collections::HashMap;
 use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+const POD_SORT_SETTING: &str = "pod_sort";
+const VOLUME_SETTING: &str = "volume";
+const NORMALIZE_SETTING: &str = "normalize_loudness";
+const SKIP_SILENCE_SETTING: &str = "skip_silence";
+/// Sleep timer lengths in minutes, in the order the sleep key cycles through them
+const SLEEP_MINUTES: [u64; 5] = [15, 30, 45, 60, 90];
+/// How long playback fades out before the sleep timer stops it
+const SLEEP_FADE_SECS: f32 = 10.0;
+/// Intro and outro lengths in seconds a pod cycles through, starting from none
+const INTRO_OUTRO_PRESETS: [i32; 7] = [0, 15, 30, 45, 60, 90, 120];
+/// Skip intervals a pod cycles through before going back to the configured ones
+const SKIP_PRESETS: [i32; 7] = [5, 10, 15, 20, 30, 45, 60];
+/// More than playback moves between two ticks at any speed, a bigger jump is a seek
+const MAX_TICK_ADVANCE_SECS: f32 = 2.0;
 
 #[derive(Clone)]
 pub struct StatefulList<T> {
@@ -25,6 +53,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -39,6 +70,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -56,12 +90,65 @@ impl<T> StatefulList<T> {
 pub enum NavigationStack {
     Main,
     Episodes,
+    Search,
+    Inbox,
+    Queue,
 }
 
 pub enum InputMode {
     Normal,
     Editing,
     Help,
+    Search,
+    Filter,
+    GoTo,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EpisodeFilter {
+    All,
+    Unplayed,
+    InProgress,
+    Downloaded,
+    Finished,
+}
+
+impl EpisodeFilter {
+    pub fn next(self) -> EpisodeFilter {
+        match self {
+            EpisodeFilter::All => EpisodeFilter::Unplayed,
+            EpisodeFilter::Unplayed => EpisodeFilter::InProgress,
+            EpisodeFilter::InProgress => EpisodeFilter::Downloaded,
+            EpisodeFilter::Downloaded => EpisodeFilter::Finished,
+            EpisodeFilter::Finished => EpisodeFilter::All,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EpisodeFilter::All => "All",
+            EpisodeFilter::Unplayed => "Unplayed",
+            EpisodeFilter::InProgress => "In progress",
+            EpisodeFilter::Downloaded => "Downloaded",
+            EpisodeFilter::Finished => "Finished",
+        }
+    }
+
+    pub fn matches(self, ep: &Episode) -> bool {
+        // Same threshold as the full progress icon in the episode list
+        let finished = ep.played
+            || ep
+                .duration
+                .map(|duration| duration > 0 && ep.timestamp / duration as f32 >= 0.9)
+                .unwrap_or(false);
+        match self {
+            EpisodeFilter::All => true,
+            EpisodeFilter::Unplayed => !finished && ep.timestamp == 0.0,
+            EpisodeFilter::InProgress => !finished && ep.timestamp > 0.0,
+            EpisodeFilter::Downloaded => ep.downloaded,
+            EpisodeFilter::Finished => finished,
+        }
+    }
 }
 
 pub enum InputField {
@@ -69,43 +156,187 @@ pub enum InputField {
     Url,
 }
 
+/// When the sleep timer stops playback
+#[derive(Clone, Copy, PartialEq)]
+pub enum SleepTimer {
+    /// After a number of minutes, at the given instant
+    Minutes(u64, Instant),
+    /// When playback reaches the next timestamp in the show notes, in seconds
+    EndOfChapter(f32),
+    EndOfEpisode,
+}
+
 pub struct App {
     pub pods: StatefulList<Pod>,
+    pub pod_sort: PodSort,
+    pub pod_stats: HashMap<i32, PodStats>,
     pub episodes: Option<StatefulList<Episode>>,
+    all_episodes: Vec<Episode>,
+    pub episode_filter: EpisodeFilter,
+    pub episode_query: String,
+    pub search_results: Option<StatefulList<Episode>>,
+    pub inbox: Option<StatefulList<Episode>>,
+    pub queue: StatefulList<Episode>,
+    // Listening time silence skipping saved over all episodes, in seconds
+    pub total_time_saved: f32,
+    inbox_days: u32,
+    skip_intervals: SkipIntervals,
     io_tx: Option<Sender<IoEvent>>,
+    conn: SqliteConnection,
     pub is_loading: bool,
     pub is_downloading: bool,
     pub is_refreshing: bool,
     pub navigation_stack: NavigationStack,
     pub player: Player,
     pub active_pod_id: i32,
-    pub show_popup: bool,
     pub show_description: bool,
+    // Times found in the show notes of the episode in the description popup
+    pub timestamps: StatefulList<Timestamp>,
+    pub sleep_timer: Option<SleepTimer>,
+    positions: PositionTracker,
+    sleep_fading: bool,
+    // Position of the playing episode on the previous tick, to tell playing into
+    // the outro from seeking into it
+    last_tick_position: Option<f32>,
     pub input_pod_name: String,
     pub input_pod_url: String,
     pub input_mode: InputMode,
     pub input_field: InputField,
+    pub search_query: String,
+    pub goto_input: String,
+    pub error_message: Option<String>,
 }
 
 impl App {
-    pub fn new(io_tx: Sender<IoEvent>, player: Player, pods_db: Vec<Pod>) -> App {
-        App {
-            pods: StatefulList::with_items(pods_db.clone()),
+    pub fn new(
+        io_tx: Sender<IoEvent>,
+        player: Player,
+        conn: SqliteConnection,
+        inbox_days: u32,
+        skip_intervals: SkipIntervals,
+        positions: PositionTracker,
+    ) -> App {
+        let mut app = App {
+            pods: StatefulList::with_items(Vec::new()),
+            pod_sort: PodSort::Added,
+            pod_stats: HashMap::new(),
             episodes: None,
+            all_episodes: Vec::new(),
+            episode_filter: EpisodeFilter::All,
+            episode_query: String::new(),
+            search_results: None,
+            inbox: None,
+            queue: StatefulList::with_items(Vec::new()),
+            total_time_saved: 0.0,
+            inbox_days,
+            skip_intervals,
             io_tx: Some(io_tx),
+            conn,
             is_loading: false,
             is_downloading: false,
             is_refreshing: false,
             navigation_stack: NavigationStack::Main,
             player,
             active_pod_id: 0,
-            show_popup: false,
             show_description: false,
+            timestamps: StatefulList::with_items(Vec::new()),
+            sleep_timer: None,
+            positions,
+            sleep_fading: false,
+            last_tick_position: None,
             input_pod_name: String::new(),
             input_pod_url: String::new(),
             input_mode: InputMode::Normal,
             input_field: InputField::Name,
+            search_query: String::new(),
+            goto_input: String::new(),
+            error_message: None,
+        };
+        let pod_sort = get_setting(&mut app.conn, POD_SORT_SETTING);
+        if let Some(Some(pod_sort)) = app.report(pod_sort) {
+            app.pod_sort = PodSort::parse(&pod_sort);
         }
+        let normalize = get_setting(&mut app.conn, NORMALIZE_SETTING);
+        if let Some(Some(normalize)) = app.report(normalize) {
+            app.player.set_normalize_loudness(normalize == "true");
+        }
+        let skip_silence = get_setting(&mut app.conn, SKIP_SILENCE_SETTING);
+        if let Some(Some(skip_silence)) = app.report(skip_silence) {
+            app.player.set_skip_silence(skip_silence == "true");
+        }
+        let total_time_saved = get_total_time_saved(&mut app.conn);
+        if let Some(total_time_saved) = app.report(total_time_saved) {
+            app.total_time_saved = total_time_saved;
+        }
+        let volume = get_setting(&mut app.conn, VOLUME_SETTING);
+        if let Some(Some(volume)) = app.report(volume) {
+            if let Ok(volume) = volume.parse() {
+                app.player.set_volume(volume);
+            }
+        }
+        app.reload_pods();
+        app.reload_queue();
+        app
+    }
+
+    /// Reloads the pod list in the current sort order, keeping the selected pod selected
+    pub fn reload_pods(&mut self) {
+        let selected_id = self
+            .pods
+            .state
+            .selected()
+            .and_then(|i| self.pods.items.get(i))
+            .map(|pod| pod.id);
+        let result = get_pods(&mut self.conn, self.pod_sort);
+        if let Some(pods) = self.report(result) {
+            let mut list = StatefulList::with_items(pods);
+            let index = selected_id.and_then(|id| list.items.iter().position(|pod| pod.id == id));
+            list.state.select(index);
+            self.pods = list;
+        }
+        self.reload_pod_stats();
+    }
+
+    /// Refreshes the episode counts shown next to each pod
+    pub fn reload_pod_stats(&mut self) {
+        let result = get_pod_stats(&mut self.conn);
+        if let Some(stats) = self.report(result) {
+            self.pod_stats = stats;
+        }
+    }
+
+    /// Switches to the next sort order of the pod list, or of the active pod's episodes
+    pub fn cycle_sort(&mut self) {
+        match self.navigation_stack {
+            NavigationStack::Main => {
+                self.pod_sort = self.pod_sort.next();
+                let result = set_setting(&mut self.conn, POD_SORT_SETTING, self.pod_sort.as_str());
+                self.report(result);
+                self.reload_pods();
+            }
+            NavigationStack::Episodes => {
+                let sort = self.active_episode_sort().next();
+                let result = set_episode_sort(&mut self.conn, self.active_pod_id, sort);
+                if self.report(result).is_some() {
+                    if let Some(pod) = self
+                        .pods
+                        .items
+                        .iter_mut()
+                        .find(|pod| pod.id == self.active_pod_id)
+                    {
+                        pod.episode_sort = sort.as_str().to_string();
+                    }
+                    self.set_active_pod(self.active_pod_id);
+                }
+            }
+            NavigationStack::Search | NavigationStack::Inbox | NavigationStack::Queue => {}
+        }
+    }
+
+    pub fn active_episode_sort(&self) -> EpisodeSort {
+        self.active_pod()
+            .map(|pod| EpisodeSort::parse(&pod.episode_sort))
+            .unwrap_or(EpisodeSort::DateDesc)
     }
 
     pub fn dispatch(&mut self, action: IoEvent) {
@@ -118,32 +349,267 @@ impl App {
         }
     }
 
+    /// Keeps failures on screen instead of panicking with the terminal in raw mode
+    pub fn report<T, E: std::error::Error>(&mut self, result: Result<T, E>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                let mut message = e.to_string();
+                let mut source = e.source();
+                while let Some(cause) = source {
+                    message = format!("{}: {}", message, cause);
+                    source = cause.source();
+                }
+                self.error_message = Some(message);
+                None
+            }
+        }
+    }
+
     pub fn set_active_pod(&mut self, id: i32) {
+        if id != self.active_pod_id {
+            self.episode_query.clear();
+            self.episodes = None;
+        }
         self.active_pod_id = id;
-        let mut conn = establish_connection();
-        let eps = get_episodes_for_pod(&mut conn, id);
-        self.episodes = Some(StatefulList::with_items(eps));
+        let sort = self.active_episode_sort();
+        let eps = get_episodes_for_pod(&mut self.conn, id, sort);
+        if let Some(eps) = self.report(eps) {
+            self.all_episodes = eps;
+            self.apply_episode_filter();
+        }
+    }
+
+    /// Rebuilds the visible episode list from the filter and title search, keeping the
+    /// selected episode selected when it is still visible
+    pub fn apply_episode_filter(&mut self) {
+        let selected_id = self
+            .episodes
+            .as_ref()
+            .and_then(|list| list.state.selected().and_then(|i| list.items.get(i)))
+            .map(|ep| ep.id);
+        let query = self.episode_query.to_lowercase();
+        let items: Vec<Episode> = self
+            .all_episodes
+            .iter()
+            .filter(|ep| {
+                self.episode_filter.matches(ep) && ep.title.to_lowercase().contains(&query)
+            })
+            .cloned()
+            .collect();
+        let mut list = StatefulList::with_items(items);
+        if let Some(id) = selected_id {
+            let index = list.items.iter().position(|ep| ep.id == id);
+            let fallback = if list.items.is_empty() { None } else { Some(0) };
+            list.state.select(index.or(fallback));
+        }
+        self.episodes = Some(list);
+    }
+
+    pub fn cycle_episode_filter(&mut self) {
+        self.episode_filter = self.episode_filter.next();
+        self.apply_episode_filter();
+    }
+
+    pub fn start_episode_query(&mut self) {
+        self.input_mode = InputMode::Filter;
+    }
+
+    pub fn push_episode_query(&mut self, c: char) {
+        self.episode_query.push(c);
+        self.apply_episode_filter();
+    }
+
+    pub fn pop_episode_query(&mut self) {
+        self.episode_query.pop();
+        self.apply_episode_filter();
+    }
+
+    pub fn clear_episode_query(&mut self) {
+        self.input_mode = InputMode::Normal;
+        self.episode_query.clear();
+        self.apply_episode_filter();
+    }
+
+    pub fn episodes_title(&self) -> String {
+        let mut title = format!("Episodes ({})", self.active_episode_sort().label());
+        if self.episode_filter != EpisodeFilter::All {
+            title = format!("{} [{}]", title, self.episode_filter.label());
+        }
+        if self
+            .active_pod()
+            .map(|pod| pod.continuous_play)
+            .unwrap_or(false)
+        {
+            title = format!("{} [continuous]", title);
+        }
+        if !self.episode_query.is_empty() || matches!(self.input_mode, InputMode::Filter) {
+            title = format!("{} /{}", title, self.episode_query);
+        }
+        title
+    }
+
+    fn active_pod(&self) -> Option<&Pod> {
+        self.pods
+            .items
+            .iter()
+            .find(|pod| pod.id == self.active_pod_id)
+    }
+
+    /// The highlighted pod, or the open one in the episode list
+    fn targeted_pod(&self) -> Option<&Pod> {
+        match self.navigation_stack {
+            NavigationStack::Main => self
+                .pods
+                .state
+                .selected()
+                .and_then(|i| self.pods.items.get(i)),
+            NavigationStack::Episodes => self.active_pod(),
+            _ => None,
+        }
+    }
+
+    pub fn toggle_continuous_play(&mut self) {
+        let (pod_id, enabled) = match self.targeted_pod() {
+            Some(pod) => (pod.id, !pod.continuous_play),
+            None => return,
+        };
+        let result = set_continuous_play(&mut self.conn, pod_id, enabled);
+        if self.report(result).is_some() {
+            if let Some(pod) = self.pods.items.iter_mut().find(|pod| pod.id == pod_id) {
+                pod.continuous_play = enabled;
+            }
+        }
+    }
+
+    /// Moves the targeted pod's forward or back skip interval to the next preset,
+    /// going back to the configured interval after the last one
+    pub fn cycle_skip_interval(&mut self, forward: bool) {
+        let (pod_id, mut skip_forward, mut skip_back) = match self.targeted_pod() {
+            Some(pod) => (pod.id, pod.skip_forward, pod.skip_back),
+            None => return,
+        };
+        let interval = if forward {
+            &mut skip_forward
+        } else {
+            &mut skip_back
+        };
+        *interval = match *interval {
+            None => Some(SKIP_PRESETS[0]),
+            Some(secs) => SKIP_PRESETS.iter().copied().find(|&preset| preset > secs),
+        };
+        let result = set_skip_intervals(&mut self.conn, pod_id, skip_forward, skip_back);
+        if self.report(result).is_some() {
+            if let Some(pod) = self.pods.items.iter_mut().find(|pod| pod.id == pod_id) {
+                pod.skip_forward = skip_forward;
+                pod.skip_back = skip_back;
+            }
+        }
+    }
+
+    /// Skips the playing episode ahead or back by its pod's interval, or the long one
+    pub fn skip(&mut self, forward: bool, long: bool) {
+        let pod = self.playing_pod();
+        let intervals = self.skip_intervals;
+        let secs = match (forward, long) {
+            (true, true) => intervals.long_forward as i32,
+            (false, true) => -(intervals.long_back as i32),
+            (true, false) => pod
+                .and_then(|pod| pod.skip_forward)
+                .unwrap_or(intervals.forward as i32),
+            (false, false) => -pod
+                .and_then(|pod| pod.skip_back)
+                .unwrap_or(intervals.back as i32),
+        };
+        self.player.skip(secs as f64);
+    }
+
+    /// Moves the targeted pod's intro or outro to the next length, back to none after the last
+    pub fn cycle_intro_outro(&mut self, intro: bool) {
+        let (pod_id, mut skip_intro, mut skip_outro) = match self.targeted_pod() {
+            Some(pod) => (pod.id, pod.skip_intro, pod.skip_outro),
+            None => return,
+        };
+        let length = if intro {
+            &mut skip_intro
+        } else {
+            &mut skip_outro
+        };
+        *length = INTRO_OUTRO_PRESETS
+            .iter()
+            .copied()
+            .find(|&preset| preset > *length)
+            .unwrap_or(0);
+        let result = set_intro_outro(&mut self.conn, pod_id, skip_intro, skip_outro);
+        if self.report(result).is_some() {
+            if let Some(pod) = self.pods.items.iter_mut().find(|pod| pod.id == pod_id) {
+                pod.skip_intro = skip_intro;
+                pod.skip_outro = skip_outro;
+            }
+        }
+    }
+
+    fn playing_pod(&self) -> Option<&Pod> {
+        let pod_id = self.player.selected_track.as_ref()?.pod_id;
+        self.pods.items.iter().find(|pod| pod.id == pod_id)
+    }
+
+    /// Where the playing episode ends, in seconds, leaving out its pod's outro
+    fn playing_end(&self) -> Option<f32> {
+        let outro = self.playing_pod().map(|pod| pod.skip_outro).unwrap_or(0);
+        Some(self.player.duration()? - outro as f32).filter(|&end| end > 0.0)
+    }
+
+    /// Ends the playing episode when playback runs into its pod's outro. A seek
+    /// that lands in the outro plays on from there.
+    fn check_outro(&mut self) {
+        let position = self.player.get_current_timestamp();
+        let last_position = self.last_tick_position.replace(position);
+        if self.playing_pod().map(|pod| pod.skip_outro).unwrap_or(0) == 0
+            || self.player.get_playback_state() != PlaybackState::Playing
+        {
+            return;
+        }
+        if let (Some(end), Some(last_position)) = (self.playing_end(), last_position) {
+            if last_position < end
+                && position >= end
+                && position - last_position <= MAX_TICK_ADVANCE_SECS
+            {
+                self.player.finish();
+            }
+        }
     }
 
     // TODO: Cleanup audio files on disk
     pub fn delete_pod_and_episodes(&mut self) {
         if let Some(index) = self.pods.state.selected() {
-            let pod = &self.pods.items[index];
-            let mut conn = establish_connection();
-            delete_pod(&mut conn, pod.id);
-            let pods = get_pods(&mut conn);
-            self.pods = StatefulList::with_items(pods);
+            let pod_id = self.pods.items[index].id;
+            let result = delete_pod(&mut self.conn, pod_id);
+            if self.report(result).is_some() {
+                let playing = self.player.selected_track.as_ref();
+                if playing.is_some_and(|track| track.pod_id == pod_id) {
+                    self.player.unload();
+                }
+                self.reload_pods();
+                // The db took the pod's episodes out of the queue as well
+                self.reload_queue();
+            }
         }
     }
 
     pub fn refresh_pod(&mut self) {
         if let Some(index) = self.pods.state.selected() {
             self.is_refreshing = true;
-            return self.dispatch(IoEvent::GetPodUpdates(self.pods.items[index].clone()));
+            self.dispatch(IoEvent::GetPodUpdates(self.pods.items[index].clone()))
         }
     }
 
     pub fn back(&mut self) {
+        if let NavigationStack::Episodes = self.navigation_stack {
+            let result = clear_new_episodes(&mut self.conn, self.active_pod_id);
+            self.report(result);
+        }
+        self.reload_pod_stats();
         self.navigation_stack = NavigationStack::Main;
     }
 
@@ -162,90 +628,665 @@ impl App {
 
     pub fn save_timestamp(&mut self) {
         if let Some(selected_track) = &self.player.selected_track {
-            let mut conn = establish_connection();
-            let updated_ep = set_timestamp_on_episode(
-                &mut conn,
-                selected_track.id.clone(),
-                self.player.get_current_timestamp(),
-            );
+            let episode_id = selected_track.id;
+            let ts = self.player.get_current_timestamp();
+            let result = set_timestamp_on_episode(&mut self.conn, episode_id, ts);
+            let updated_ep = match self.report(result) {
+                Some(updated_ep) => updated_ep,
+                None => return,
+            };
             self.player.selected_track = Some(updated_ep.clone());
-            if let Some(data) = &mut self.episodes {
-                let index = data.items.iter().position(|x| x.id == updated_ep.id);
-                if let Some(i) = index {
-                    data.items[i] = updated_ep.clone();
+            self.update_episode_in_lists(&updated_ep);
+        }
+    }
+
+    /// Saves the position of a playing episode, so a crash loses little progress
+    pub fn save_playing_position(&mut self) {
+        if self.player.get_playback_state() == PlaybackState::Playing {
+            self.save_timestamp();
+        }
+    }
+
+    /// Called on every tick of the ui loop
+    pub fn on_tick(&mut self) {
+        if let Some(episode_id) = self.player.selected_track.as_ref().map(|track| track.id) {
+            let timestamp = self.player.get_current_timestamp();
+            self.positions.update(episode_id, timestamp);
+        }
+        if self.player.has_finished() {
+            if let Some(finished) = self.player.selected_track.clone() {
+                self.set_played(finished.id, true);
+                if self.sleep_timer == Some(SleepTimer::EndOfEpisode) {
+                    self.cancel_sleep_timer();
+                } else {
+                    self.play_next(&finished);
                 }
             }
         }
+        self.check_outro();
+        self.check_sleep_timer();
+        if let Some(saved) = self.player.skip_silence() {
+            self.add_time_saved(saved);
+        }
+    }
+
+    /// Moves the sleep timer to the next setting: longer and longer timers, the end
+    /// of the chapter when the show notes have timestamps, the end of the episode, off
+    pub fn cycle_sleep_timer(&mut self) {
+        let track = match &self.player.selected_track {
+            Some(track) => track.clone(),
+            None => return,
+        };
+        let position = self.player.get_current_timestamp();
+        let chapter_end = find_timestamps(&track.description)
+            .into_iter()
+            .map(|timestamp| timestamp.secs as f32)
+            .filter(|&secs| secs > position)
+            .reduce(f32::min);
+        let after_minutes = match chapter_end {
+            Some(end) => SleepTimer::EndOfChapter(end),
+            None => SleepTimer::EndOfEpisode,
+        };
+        let minutes = |minutes: u64| {
+            SleepTimer::Minutes(minutes, Instant::now() + Duration::from_secs(minutes * 60))
+        };
+        let next = match self.sleep_timer {
+            None => Some(minutes(SLEEP_MINUTES[0])),
+            Some(SleepTimer::Minutes(current, _)) => Some(
+                SLEEP_MINUTES
+                    .iter()
+                    .find(|&&preset| preset > current)
+                    .map(|&preset| minutes(preset))
+                    .unwrap_or(after_minutes),
+            ),
+            Some(SleepTimer::EndOfChapter(_)) => Some(SleepTimer::EndOfEpisode),
+            Some(SleepTimer::EndOfEpisode) => None,
+        };
+        self.cancel_sleep_timer();
+        self.sleep_timer = next;
+    }
+
+    fn cancel_sleep_timer(&mut self) {
+        self.sleep_timer = None;
+        if self.sleep_fading {
+            self.sleep_fading = false;
+            self.player.apply_loudness_gain();
+        }
+    }
+
+    /// Wall clock seconds until the sleep timer stops playback, `None` when off or
+    /// when the end of the episode isn't known
+    fn sleep_remaining(&mut self) -> Option<f32> {
+        let timer = self.sleep_timer?;
+        let position = self.player.get_current_timestamp();
+        let end = match timer {
+            SleepTimer::Minutes(_, deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                return Some(remaining.as_secs_f32());
+            }
+            SleepTimer::EndOfChapter(end) => end,
+            SleepTimer::EndOfEpisode => self.playing_end()?,
+        };
+        Some((end - position).max(0.0) / self.player.speed())
+    }
+
+    /// Fades playback out as the sleep timer runs out, then pauses and saves the position
+    fn check_sleep_timer(&mut self) {
+        let remaining = match self.sleep_remaining() {
+            Some(remaining) => remaining,
+            None => return,
+        };
+        // The end of an episode stops playback by itself, see on_tick
+        if remaining <= 0.0 && self.sleep_timer != Some(SleepTimer::EndOfEpisode) {
+            if self.player.get_playback_state() == PlaybackState::Playing {
+                self.player.toggle_playback();
+            }
+            self.save_timestamp();
+            self.cancel_sleep_timer();
+            return;
+        }
+        if remaining <= SLEEP_FADE_SECS && !self.sleep_fading {
+            self.sleep_fading = true;
+            self.player.fade_out(Duration::from_secs_f32(remaining));
+        }
+    }
+
+    /// What the sleep timer waits for, for the player
+    pub fn sleep_timer_label(&self) -> Option<String> {
+        let label = match self.sleep_timer? {
+            SleepTimer::Minutes(_, deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now()).as_secs();
+                format!("sleep in {}:{:0>2}", remaining / 60, remaining % 60)
+            }
+            SleepTimer::EndOfChapter(_) => String::from("sleep after chapter"),
+            SleepTimer::EndOfEpisode => String::from("sleep after episode"),
+        };
+        Some(label)
+    }
+
+    fn add_time_saved(&mut self, secs: f32) {
+        let episode_id = match &self.player.selected_track {
+            Some(track) => track.id,
+            None => return,
+        };
+        let result = add_time_saved(&mut self.conn, episode_id, secs);
+        if let Some(updated_ep) = self.report(result) {
+            self.total_time_saved += secs;
+            if let Some(track) = self.player.selected_track.as_mut() {
+                track.time_saved = updated_ep.time_saved;
+            }
+            self.update_episode_in_lists(&updated_ep);
+        }
+    }
+
+    /// The highlighted episode of the episode list or the search results
+    pub fn selected_episode(&self) -> Option<&Episode> {
+        let list = match self.navigation_stack {
+            NavigationStack::Episodes => self.episodes.as_ref(),
+            NavigationStack::Search => self.search_results.as_ref(),
+            NavigationStack::Inbox => self.inbox.as_ref(),
+            NavigationStack::Queue => Some(&self.queue),
+            NavigationStack::Main => None,
+        }?;
+        list.state.selected().and_then(|i| list.items.get(i))
+    }
+
+    pub fn toggle_played(&mut self) {
+        if let Some((episode_id, played)) = self.selected_episode().map(|ep| (ep.id, ep.played)) {
+            self.set_played(episode_id, !played);
+        }
+    }
+
+    fn set_played(&mut self, episode_id: i32, played: bool) {
+        let result = set_played_on_episode(&mut self.conn, episode_id, played);
+        if let Some(updated_ep) = self.report(result) {
+            if let Some(track) = self.player.selected_track.as_mut() {
+                if track.id == updated_ep.id {
+                    track.played = updated_ep.played;
+                }
+            }
+            self.update_episode_in_lists(&updated_ep);
+            if self.episodes.is_some() {
+                self.apply_episode_filter();
+            }
+        }
+    }
+
+    /// Marks every episode of the active pod published before the highlighted one as played
+    pub fn mark_older_as_played(&mut self) {
+        if !matches!(self.navigation_stack, NavigationStack::Episodes) {
+            return;
+        }
+        if let Some(before) = self.selected_episode().map(|ep| ep.pub_timestamp) {
+            let result = mark_older_as_played(&mut self.conn, self.active_pod_id, before);
+            if self.report(result).is_some() {
+                self.set_active_pod(self.active_pod_id);
+            }
+        }
+    }
+
+    /// Keeps the episode lists, search results and inbox in sync with the db
+    pub fn update_episode_in_lists(&mut self, episode: &Episode) {
+        let index = self.all_episodes.iter().position(|x| x.id == episode.id);
+        if let Some(i) = index {
+            self.all_episodes[i] = episode.clone();
+        }
+        for list in [
+            self.episodes.as_mut(),
+            self.search_results.as_mut(),
+            self.inbox.as_mut(),
+            Some(&mut self.queue),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let index = list.items.iter().position(|x| x.id == episode.id);
+            if let Some(i) = index {
+                list.items[i] = episode.clone();
+            }
+        }
     }
 
     pub fn handle_enter_pod(&mut self) {
         self.navigation_stack = NavigationStack::Episodes;
         if let Some(index) = self.pods.state.selected() {
-            let pod = &self.pods.items[index];
-            let mut conn = establish_connection();
-            let updated_pod = get_pod(&mut conn, pod.id);
+            let pod_id = self.pods.items[index].id;
+            let result = get_pod(&mut self.conn, pod_id);
+            let updated_pod = match self.report(result) {
+                Some(updated_pod) => updated_pod,
+                None => return,
+            };
             if !updated_pod.downloaded {
                 return self.dispatch(IoEvent::GetPodEpisodes(self.pods.items[index].clone()));
             }
-            self.episodes = Some(StatefulList::with_items(get_episodes_for_pod(
-                &mut conn, pod.id,
-            )));
+            self.set_active_pod(pod_id);
         }
     }
 
     pub fn handle_enter_episode(&mut self) {
-        if let Some(data) = self.episodes.clone() {
+        if let Some(data) = &self.episodes {
             if let Some(index) = data.state.selected() {
-                self.save_timestamp();
-                let ep = &data.items[index];
-                let mut conn = establish_connection();
-                let updated_ep = get_episode(&mut conn, ep.id);
-                if !updated_ep.downloaded {
-                    self.is_downloading = true;
-                    return self.dispatch(IoEvent::DownloadEpisodeAudio(
-                        data.items[index].clone(),
-                        0.0,
-                    ));
-                }
-                if let Err(_metadata) = fs::metadata(&updated_ep.audio_filepath.as_ref().unwrap()) {
-                    self.is_downloading = true;
-                    return self.dispatch(IoEvent::DownloadEpisodeAudio(
-                        data.items[index].clone(),
-                        updated_ep.timestamp,
-                    ));
-                }
-                self.player.selected_track = Some(updated_ep.clone());
-                self.player.play();
-                self.player.seek(updated_ep.timestamp);
+                let ep_id = data.items[index].id;
+                self.start_episode(ep_id);
             }
         }
     }
 
-    pub fn play_episode(&mut self, episode: Episode, timestamp: f32) {
+    /// Plays an episode from its saved position, downloading the audio first when needed
+    pub fn start_episode(&mut self, ep_id: i32) {
+        self.start_episode_at(ep_id, None);
+    }
+
+    /// Plays an episode from `timestamp`, or its saved position when `None`
+    fn start_episode_at(&mut self, ep_id: i32, timestamp: Option<f32>) {
+        self.save_timestamp();
+        // A queued episode leaves the queue once it is playing
+        if self.queue.items.iter().any(|ep| ep.id == ep_id) {
+            let result = remove_from_queue(&mut self.conn, ep_id);
+            self.report(result);
+            self.reload_queue();
+        }
+        let result = get_episode(&mut self.conn, ep_id);
+        let updated_ep = match self.report(result) {
+            Some(updated_ep) => updated_ep,
+            None => return,
+        };
+        let needs_download = !updated_ep.downloaded || updated_ep.audio_changed;
+        // Only a fresh start skips the intro, a chosen position is played as is
+        let (timestamp, skip_intro) = match timestamp {
+            Some(timestamp) => (timestamp, false),
+            None if needs_download => (0.0, true),
+            None => (updated_ep.timestamp, updated_ep.timestamp == 0.0),
+        };
+        if needs_download {
+            self.is_downloading = true;
+            let event = IoEvent::DownloadEpisodeAudio(updated_ep, timestamp, skip_intro);
+            return self.dispatch(event);
+        }
+        if let Err(_metadata) = fs::metadata(updated_ep.audio_filepath.as_ref().unwrap()) {
+            self.is_downloading = true;
+            let event = IoEvent::DownloadEpisodeAudio(updated_ep, timestamp, skip_intro);
+            return self.dispatch(event);
+        }
+        self.play_episode(updated_ep, timestamp, skip_intro);
+    }
+
+    /// Plays an episode at the default speed of its pod, past the pod's intro
+    /// when `skip_intro` is set
+    pub fn play_episode(&mut self, episode: Episode, mut timestamp: f32, skip_intro: bool) {
+        let (speed, intro) = self
+            .pods
+            .items
+            .iter()
+            .find(|pod| pod.id == episode.pod_id)
+            .map(|pod| (pod.playback_speed, pod.skip_intro))
+            .unwrap_or((1.0, 0));
+        let long_enough = episode.duration.is_none_or(|duration| duration > intro);
+        if skip_intro && intro > 0 && long_enough {
+            timestamp = intro as f32;
+        }
+        self.player.set_speed(speed);
+        // A chapter of the previous episode means nothing in this one
+        if let Some(SleepTimer::EndOfChapter(_)) = self.sleep_timer {
+            self.sleep_timer = None;
+        }
+        self.sleep_fading = false;
+        self.last_tick_position = None;
         self.player.selected_track = Some(episode.clone());
         self.player.play();
         self.player.seek(timestamp);
-        if let Some(data) = &mut self.episodes {
-            let index = data.items.iter().position(|x| x.id == episode.id);
-            if let Some(i) = index {
-                data.items[i] = episode.clone();
+        self.update_episode_in_lists(&episode);
+        // Older downloads and local files were never analysed. Silences are stored by
+        // every analysis, a gain only when the audio could be measured.
+        if episode.silences.is_none() {
+            self.queue_analysis(&episode);
+        }
+    }
+
+    /// Speeds playback up or down, remembering the new speed for the episode's pod
+    pub fn change_speed(&mut self, delta: f32) {
+        let pod_id = match &self.player.selected_track {
+            Some(track) => track.pod_id,
+            None => return,
+        };
+        let speed = self.player.set_speed(self.player.speed() + delta);
+        let result = set_playback_speed(&mut self.conn, pod_id, speed);
+        if self.report(result).is_some() {
+            if let Some(pod) = self.pods.items.iter_mut().find(|pod| pod.id == pod_id) {
+                pod.playback_speed = speed;
+            }
+        }
+    }
+
+    /// Changes the volume by `delta` percent and remembers it for the next session
+    pub fn change_volume(&mut self, delta: i32) {
+        let volume = self.player.set_volume(self.player.volume() as i32 + delta);
+        let result = set_setting(&mut self.conn, VOLUME_SETTING, &volume.to_string());
+        self.report(result);
+    }
+
+    pub fn toggle_loudness_normalization(&mut self) {
+        let enabled = !self.player.normalizes_loudness();
+        self.player.set_normalize_loudness(enabled);
+        let result = set_setting(&mut self.conn, NORMALIZE_SETTING, &enabled.to_string());
+        self.report(result);
+    }
+
+    pub fn toggle_skip_silence(&mut self) {
+        let enabled = !self.player.skips_silence();
+        self.player.set_skip_silence(enabled);
+        let result = set_setting(&mut self.conn, SKIP_SILENCE_SETTING, &enabled.to_string());
+        self.report(result);
+    }
+
+    /// Queues the loudness and silence analysis of downloaded audio,
+    /// without showing the loading indicator
+    pub fn queue_analysis(&mut self, episode: &Episode) {
+        if episode.audio_filepath.is_none() {
+            return;
+        }
+        let result = match &self.io_tx {
+            Some(io_tx) => io_tx.send(IoEvent::AnalyseEpisode(episode.clone())),
+            None => return,
+        };
+        self.report(result);
+    }
+
+    pub fn audio_analysed(&mut self, episode: &Episode) {
+        if let Some(track) = self.player.selected_track.as_mut() {
+            if track.id == episode.id {
+                track.loudness_gain = episode.loudness_gain;
+                track.silences = episode.silences.clone();
+                self.player.apply_loudness_gain();
+                self.player.load_silences();
+            }
+        }
+        self.update_episode_in_lists(episode);
+    }
+
+    pub fn start_goto(&mut self) {
+        if self.player.selected_track.is_some() {
+            self.goto_input.clear();
+            self.input_mode = InputMode::GoTo;
+        }
+    }
+
+    /// Seeks to the position typed into the go to prompt
+    pub fn goto(&mut self) {
+        self.input_mode = InputMode::Normal;
+        match parse_position(&self.goto_input, self.player.duration()) {
+            Some(position) => self.player.seek(position),
+            None => {
+                self.error_message = Some(format!(
+                    "can't go to {}, expected hh:mm:ss, mm:ss or a percentage of a track with a known length",
+                    self.goto_input
+                ))
+            }
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.navigation_stack = NavigationStack::Search;
+        self.input_mode = InputMode::Search;
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.input_mode = InputMode::Normal;
+        if self.search_results.is_none() {
+            self.navigation_stack = NavigationStack::Main;
+        }
+    }
+
+    pub fn search(&mut self) {
+        self.input_mode = InputMode::Normal;
+        let result = search_episodes(&mut self.conn, &self.search_query);
+        if let Some(eps) = self.report(result) {
+            let mut results = StatefulList::with_items(eps);
+            if !results.items.is_empty() {
+                results.state.select(Some(0));
             }
+            self.search_results = Some(results);
         }
     }
 
+    pub fn handle_enter_search_result(&mut self) {
+        if let Some(results) = &self.search_results {
+            if let Some(index) = results.state.selected() {
+                let ep_id = results.items[index].id;
+                self.start_episode(ep_id);
+            }
+        }
+    }
+
+    pub fn open_inbox(&mut self) {
+        self.navigation_stack = NavigationStack::Inbox;
+        let since = chrono::Utc::now().timestamp() - i64::from(self.inbox_days) * 24 * 60 * 60;
+        let result = get_inbox(&mut self.conn, since as i32);
+        if let Some(eps) = self.report(result) {
+            let mut inbox = StatefulList::with_items(eps);
+            if !inbox.items.is_empty() {
+                inbox.state.select(Some(0));
+            }
+            self.inbox = Some(inbox);
+        }
+    }
+
+    pub fn inbox_title(&self) -> String {
+        match self.inbox_days {
+            1 => String::from("Inbox (last day)"),
+            days => format!("Inbox (last {} days)", days),
+        }
+    }
+
+    pub fn handle_enter_inbox_episode(&mut self) {
+        if let Some(inbox) = &self.inbox {
+            if let Some(index) = inbox.state.selected() {
+                let ep_id = inbox.items[index].id;
+                self.start_episode(ep_id);
+            }
+        }
+    }
+
+    /// Downloads the highlighted episode without playing it
+    pub fn download_selected_episode(&mut self) {
+        if let Some(episode) = self.selected_episode().cloned() {
+            if episode.downloaded && !episode.audio_changed {
+                return;
+            }
+            self.is_downloading = true;
+            self.dispatch(IoEvent::DownloadEpisode(episode));
+        }
+    }
+
+    /// Hides the highlighted episode from the inbox for good
+    pub fn dismiss_inbox_episode(&mut self) {
+        let episode_id = match self.selected_episode() {
+            Some(ep) if matches!(self.navigation_stack, NavigationStack::Inbox) => ep.id,
+            _ => return,
+        };
+        let result = dismiss_episode(&mut self.conn, episode_id);
+        if self.report(result).is_none() {
+            return;
+        }
+        if let Some(inbox) = self.inbox.as_mut() {
+            let selected = inbox.state.selected().unwrap_or(0);
+            inbox.items.retain(|ep| ep.id != episode_id);
+            let index = if inbox.items.is_empty() {
+                None
+            } else {
+                Some(selected.min(inbox.items.len() - 1))
+            };
+            inbox.state.select(index);
+        }
+    }
+
+    pub fn open_queue(&mut self) {
+        self.navigation_stack = NavigationStack::Queue;
+        self.reload_queue();
+    }
+
+    /// Reloads the queue, keeping the highlighted episode highlighted
+    fn reload_queue(&mut self) {
+        let selected_id = self
+            .queue
+            .state
+            .selected()
+            .and_then(|i| self.queue.items.get(i))
+            .map(|ep| ep.id);
+        let result = get_queue(&mut self.conn);
+        if let Some(eps) = self.report(result) {
+            let mut queue = StatefulList::with_items(eps);
+            let index = selected_id.and_then(|id| queue.items.iter().position(|ep| ep.id == id));
+            let fallback = if queue.items.is_empty() {
+                None
+            } else {
+                Some(0)
+            };
+            queue.state.select(index.or(fallback));
+            self.queue = queue;
+        }
+    }
+
+    /// Adds the highlighted episode to the end of the queue
+    pub fn queue_selected_episode(&mut self) {
+        if let Some(ep_id) = self.selected_episode().map(|ep| ep.id) {
+            let result = enqueue_episode(&mut self.conn, ep_id);
+            self.report(result);
+            self.reload_queue();
+        }
+    }
+
+    pub fn remove_selected_from_queue(&mut self) {
+        if !matches!(self.navigation_stack, NavigationStack::Queue) {
+            return;
+        }
+        if let Some(ep_id) = self.selected_episode().map(|ep| ep.id) {
+            let result = remove_from_queue(&mut self.conn, ep_id);
+            self.report(result);
+            self.reload_queue();
+        }
+    }
+
+    pub fn move_selected_in_queue(&mut self, up: bool) {
+        if !matches!(self.navigation_stack, NavigationStack::Queue) {
+            return;
+        }
+        if let Some(ep_id) = self.selected_episode().map(|ep| ep.id) {
+            let result = move_in_queue(&mut self.conn, ep_id, up);
+            self.report(result);
+            self.reload_queue();
+        }
+    }
+
+    pub fn handle_enter_queue_episode(&mut self) {
+        if let Some(ep_id) = self.selected_episode().map(|ep| ep.id) {
+            self.start_episode(ep_id);
+        }
+    }
+
+    /// Follows up a finished episode with the first one of the queue, or else with the
+    /// next episode of its pod when the pod is in continuous play
+    fn play_next(&mut self, finished: &Episode) {
+        if let Some(ep_id) = self.queue.items.first().map(|ep| ep.id) {
+            return self.start_episode(ep_id);
+        }
+        let continuous = self
+            .pods
+            .items
+            .iter()
+            .any(|pod| pod.id == finished.pod_id && pod.continuous_play);
+        if !continuous {
+            return;
+        }
+        let result = get_next_episode(&mut self.conn, finished);
+        if let Some(Some(next)) = self.report(result) {
+            self.start_episode(next.id);
+        }
+    }
+
+    pub fn pod_title(&self, pod_id: i32) -> &str {
+        self.pods
+            .items
+            .iter()
+            .find(|pod| pod.id == pod_id)
+            .map(|pod| pod.title.as_str())
+            .unwrap_or("")
+    }
+
     pub fn toggle_description(&mut self) {
         self.show_description = !self.show_description;
+        self.load_timestamps();
+    }
+
+    fn selected_list_mut(&mut self) -> Option<&mut StatefulList<Episode>> {
+        match self.navigation_stack {
+            NavigationStack::Episodes => self.episodes.as_mut(),
+            NavigationStack::Search => self.search_results.as_mut(),
+            NavigationStack::Inbox => self.inbox.as_mut(),
+            NavigationStack::Queue => Some(&mut self.queue),
+            NavigationStack::Main => None,
+        }
+    }
+
+    fn load_timestamps(&mut self) {
+        let timestamps = match self.selected_episode() {
+            Some(ep) if self.show_description => find_timestamps(&ep.description),
+            _ => Vec::new(),
+        };
+        self.timestamps = StatefulList::with_items(timestamps);
+        if !self.timestamps.items.is_empty() {
+            self.timestamps.state.select(Some(0));
+        }
+    }
+
+    /// Moves through the timestamps of the open description, or through the
+    /// episodes when it has none
+    pub fn description_next(&mut self, forward: bool) {
+        if !self.timestamps.items.is_empty() {
+            match forward {
+                true => self.timestamps.next(),
+                false => self.timestamps.previous(),
+            }
+            return;
+        }
+        if let Some(list) = self.selected_list_mut() {
+            match forward {
+                true => list.next(),
+                false => list.previous(),
+            }
+        }
+        self.load_timestamps();
+    }
+
+    pub fn has_timestamps(&self) -> bool {
+        self.show_description && !self.timestamps.items.is_empty()
+    }
+
+    /// Seeks to the selected show notes timestamp, starting its episode there
+    /// when something else is playing
+    pub fn seek_to_timestamp(&mut self) {
+        let secs = match self.timestamps.state.selected() {
+            Some(index) => self.timestamps.items[index].secs as f32,
+            None => return,
+        };
+        let ep_id = match self.selected_episode() {
+            Some(ep) => ep.id,
+            None => return,
+        };
+        match &self.player.selected_track {
+            Some(track) if track.id == ep_id => self.player.seek(secs),
+            _ => self.start_episode_at(ep_id, Some(secs)),
+        }
     }
 
     pub fn create_pod(&mut self) {
         //TODO: Validate input
         //1. Validate input
         //2. Create pod in db
-        let mut conn = establish_connection();
-        let _ = create_pod(&mut conn, &self.input_pod_name, &self.input_pod_url);
+        let result = create_pod(&mut self.conn, &self.input_pod_name, &self.input_pod_url);
+        self.report(result);
         //3. Refresh pod list
-        let pods = get_pods(&mut conn);
-        self.pods = StatefulList::with_items(pods);
+        self.reload_pods();
         //4. Toggle editing mode to normal
         self.input_mode = InputMode::Normal;
         self.input_pod_name = String::from("");
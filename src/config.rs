@@ -0,0 +1,249 @@
+use error_chain::{bail, error_chain};
+use serde::Deserialize;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+error_chain! {
+    foreign_links {
+        Io(std::io::Error);
+        Toml(toml::de::Error);
+    }
+}
+
+const APP_DIR: &str = "fred_podplayer_tui";
+const LEGACY_DATABASE: &str = "poddb.db";
+const LEGACY_DATA_DIR: &str = "./data";
+const DEFAULT_INBOX_DAYS: u32 = 7;
+
+/// Where the library lives. Each setting is taken from the command line, then the
+/// environment, then `config.toml`, falling back to the XDG data directory.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub database_path: PathBuf,
+    pub data_dir: PathBuf,
+    /// How many days back the inbox looks for unplayed episodes
+    pub inbox_days: u32,
+    pub skip_intervals: SkipIntervals,
+}
+
+/// Seconds the skip keys jump, pods can override the short ones
+#[derive(Clone, Copy, Debug)]
+pub struct SkipIntervals {
+    pub forward: u32,
+    pub back: u32,
+    pub long_forward: u32,
+    pub long_back: u32,
+}
+
+impl Default for SkipIntervals {
+    fn default() -> SkipIntervals {
+        SkipIntervals {
+            forward: 10,
+            back: 10,
+            long_forward: 60,
+            long_back: 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ConfigFile {
+    database: Option<String>,
+    data_dir: Option<String>,
+    inbox_days: Option<u32>,
+    skip_forward: Option<u32>,
+    skip_back: Option<u32>,
+    long_skip_forward: Option<u32>,
+    long_skip_back: Option<u32>,
+}
+
+#[derive(Default)]
+struct Args {
+    config: Option<PathBuf>,
+    database: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load() -> Result<Config> {
+        dotenv::dotenv().ok();
+        let args = parse_args(env::args().skip(1))?;
+
+        let config_path = args
+            .config
+            .clone()
+            .or_else(|| env::var_os("PODPLAYER_CONFIG").map(PathBuf::from))
+            .unwrap_or_else(|| config_home().join(APP_DIR).join("config.toml"));
+        let config_file = read_config_file(&config_path)?;
+
+        let database_path = args
+            .database
+            .or_else(|| env::var("DATABASE_URL").ok().map(PathBuf::from))
+            .or_else(|| config_file.database.as_deref().map(expand_home))
+            .unwrap_or_else(|| data_home().join(APP_DIR).join(LEGACY_DATABASE));
+        let data_dir = args
+            .data_dir
+            .or_else(|| env::var_os("PODPLAYER_DATA_DIR").map(PathBuf::from))
+            .or_else(|| config_file.data_dir.as_deref().map(expand_home))
+            .unwrap_or_else(|| data_home().join(APP_DIR).join("data"));
+
+        if let Some(parent) = database_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        Ok(Config {
+            database_path,
+            data_dir,
+            inbox_days: config_file.inbox_days.unwrap_or(DEFAULT_INBOX_DAYS),
+            skip_intervals: skip_intervals(&config_file),
+        })
+    }
+
+    pub fn database_url(&self) -> String {
+        self.database_path.to_string_lossy().into_owned()
+    }
+
+    /// Moves a `poddb.db` left in the working directory by older versions to the
+    /// configured database path
+    pub fn migrate_legacy_database(&self) -> Result<()> {
+        let legacy_database = Path::new(LEGACY_DATABASE);
+        if !legacy_database.is_file() || self.database_path.exists() {
+            return Ok(());
+        }
+        move_path(legacy_database, &self.database_path)?;
+        // WAL side files belong to the database and have to travel with it
+        for suffix in ["-wal", "-shm"] {
+            let side_file = PathBuf::from(format!("{}{}", LEGACY_DATABASE, suffix));
+            if side_file.is_file() {
+                let target = format!("{}{}", self.database_path.to_string_lossy(), suffix);
+                move_path(&side_file, Path::new(&target))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves audio files left in `./data` by older versions into the data dir. Returns
+    /// the old and new data dir, so stored file paths still pointing into the old one
+    /// can be rewritten, or `None` when the data dir is `./data` itself.
+    /// Runs on every start, so a move that failed halfway is finished later.
+    pub fn migrate_legacy_data_dir(&self) -> Result<Option<(String, String)>> {
+        let legacy_data_dir = Path::new(LEGACY_DATA_DIR);
+        if is_same_dir(legacy_data_dir, &self.data_dir) {
+            return Ok(None);
+        }
+        if legacy_data_dir.is_dir() {
+            fs::create_dir_all(&self.data_dir)?;
+            for entry in fs::read_dir(legacy_data_dir)? {
+                let entry = entry?;
+                let target = self.data_dir.join(entry.file_name());
+                if !target.exists() {
+                    move_path(&entry.path(), &target)?;
+                }
+            }
+            // Only succeeds once everything has moved, leftovers keep the old folder around
+            let _ = fs::remove_dir(legacy_data_dir);
+        }
+        Ok(Some((
+            LEGACY_DATA_DIR.to_string(),
+            self.data_dir.to_string_lossy().into_owned(),
+        )))
+    }
+}
+
+fn skip_intervals(config_file: &ConfigFile) -> SkipIntervals {
+    let default = SkipIntervals::default();
+    SkipIntervals {
+        forward: config_file.skip_forward.unwrap_or(default.forward),
+        back: config_file.skip_back.unwrap_or(default.back),
+        long_forward: config_file
+            .long_skip_forward
+            .unwrap_or(default.long_forward),
+        long_back: config_file.long_skip_back.unwrap_or(default.long_back),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--config" => &mut parsed.config,
+            "--db" => &mut parsed.database,
+            "--data-dir" => &mut parsed.data_dir,
+            _ => bail!(
+                "unknown argument {} (expected --config, --db or --data-dir)",
+                arg
+            ),
+        };
+        match args.next() {
+            Some(value) => *target = Some(PathBuf::from(value)),
+            None => bail!("{} expects a path", arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile> {
+    if !path.is_file() {
+        return Ok(ConfigFile::default());
+    }
+    let content = fs::read_to_string(path)?;
+    toml::from_str(&content).chain_err(|| format!("invalid config file {}", path.display()))
+}
+
+/// Renames when possible, copies when the target is on another filesystem
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::remove_dir(from)?;
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+fn is_same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    // The spec says relative paths in XDG variables are invalid and should be ignored
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir().join(fallback))
+}
+
+fn data_home() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+fn config_home() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => home_dir().join(rest),
+        None => PathBuf::from(path),
+    }
+}
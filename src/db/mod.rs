@@ -3,97 +3,286 @@ extern crate dotenv;
 pub mod models;
 pub mod schema;
 
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
+use error_chain::error_chain;
 
-use models::{Episode, NewEpisode, NewPod, Pod};
+use models::{Episode, EpisodeSort, NewEpisode, NewPod, Pod, PodSort, PodStats};
 
-pub fn establish_connection() -> SqliteConnection {
-    let database_url = "poddb.db";
-    SqliteConnection::establish(&database_url).unwrap_or_else(|_| panic!("failed to connect to db"))
+use std::collections::HashMap;
+
+error_chain! {
+    foreign_links {
+        Connection(diesel::ConnectionError);
+        Query(diesel::result::Error);
+    }
+}
+
+/// Opens a long-lived connection. The UI and network thread each own one, so WAL mode and
+/// a busy timeout let them write concurrently without "database is locked" errors.
+/// SQLite only enforces foreign keys (and cascades deletes) when asked per connection.
+pub fn establish_connection(database_url: &str) -> Result<SqliteConnection> {
+    let mut conn =
+        SqliteConnection::establish(database_url).chain_err(|| "failed to connect to db")?;
+    conn.batch_execute(
+        "PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000; PRAGMA synchronous = NORMAL; \
+         PRAGMA foreign_keys = ON;",
+    )
+    .chain_err(|| "failed to configure db connection")?;
+    Ok(conn)
+}
+
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+pub fn get_pods(conn: &mut SqliteConnection, sort: PodSort) -> Result<Vec<Pod>> {
+    use diesel::dsl::sql;
+    use diesel::sql_types::Integer;
+    use schema::{episodes, pods};
+    let query = pods::table
+        .left_join(episodes::table)
+        .group_by(pods::id)
+        .select(pods::all_columns);
+    let result = match sort {
+        PodSort::Added => query.order(pods::id.asc()).load::<Pod>(conn),
+        PodSort::Title => query.order(lower(pods::title).asc()).load::<Pod>(conn),
+        PodSort::LatestEpisode => query
+            .order(diesel::dsl::max(episodes::pub_timestamp).desc())
+            .load::<Pod>(conn),
+        PodSort::UnplayedCount => query
+            .order(
+                diesel::dsl::sum(sql::<Integer>(
+                    "CASE WHEN episodes.played = 0 THEN 1 ELSE 0 END",
+                ))
+                .desc(),
+            )
+            .load::<Pod>(conn),
+    };
+    result.chain_err(|| "failed to load pods")
+}
+
+pub fn set_episode_sort(conn: &mut SqliteConnection, pod_id: i32, sort: EpisodeSort) -> Result<()> {
+    use schema::pods;
+    diesel::update(pods::table.find(pod_id))
+        .set(pods::episode_sort.eq(sort.as_str()))
+        .execute(conn)
+        .chain_err(|| "failed to save episode sort order")?;
+    Ok(())
+}
+
+pub fn set_continuous_play(conn: &mut SqliteConnection, pod_id: i32, enabled: bool) -> Result<()> {
+    use schema::pods;
+    diesel::update(pods::table.find(pod_id))
+        .set(pods::continuous_play.eq(enabled))
+        .execute(conn)
+        .chain_err(|| "failed to save continuous play")?;
+    Ok(())
 }
 
-pub fn get_pods(conn: &mut SqliteConnection) -> Vec<Pod> {
-    use schema::pods::dsl::pods;
-    pods.load::<Pod>(conn).expect("failed to load pods")
+pub fn set_playback_speed(conn: &mut SqliteConnection, pod_id: i32, speed: f32) -> Result<()> {
+    use schema::pods;
+    diesel::update(pods::table.find(pod_id))
+        .set(pods::playback_speed.eq(speed))
+        .execute(conn)
+        .chain_err(|| "failed to save playback speed")?;
+    Ok(())
+}
+
+pub fn set_skip_intervals(
+    conn: &mut SqliteConnection,
+    pod_id: i32,
+    forward: Option<i32>,
+    back: Option<i32>,
+) -> Result<()> {
+    use schema::pods;
+    diesel::update(pods::table.find(pod_id))
+        .set((pods::skip_forward.eq(forward), pods::skip_back.eq(back)))
+        .execute(conn)
+        .chain_err(|| "failed to save skip intervals")?;
+    Ok(())
 }
 
-pub fn get_pod(conn: &mut SqliteConnection, pod_id: i32) -> Pod {
+pub fn set_intro_outro(
+    conn: &mut SqliteConnection,
+    pod_id: i32,
+    intro: i32,
+    outro: i32,
+) -> Result<()> {
+    use schema::pods;
+    diesel::update(pods::table.find(pod_id))
+        .set((pods::skip_intro.eq(intro), pods::skip_outro.eq(outro)))
+        .execute(conn)
+        .chain_err(|| "failed to save intro and outro")?;
+    Ok(())
+}
+
+pub fn get_setting(conn: &mut SqliteConnection, setting_key: &str) -> Result<Option<String>> {
+    use schema::settings::dsl::*;
+    settings
+        .find(setting_key)
+        .select(value)
+        .first::<String>(conn)
+        .optional()
+        .chain_err(|| format!("failed to load setting {}", setting_key))
+}
+
+pub fn set_setting(
+    conn: &mut SqliteConnection,
+    setting_key: &str,
+    setting_value: &str,
+) -> Result<()> {
+    use schema::settings::dsl::*;
+    diesel::insert_into(settings)
+        .values((key.eq(setting_key), value.eq(setting_value)))
+        .on_conflict(key)
+        .do_update()
+        .set(value.eq(setting_value))
+        .execute(conn)
+        .chain_err(|| format!("failed to save setting {}", setting_key))?;
+    Ok(())
+}
+
+pub fn get_pod(conn: &mut SqliteConnection, pod_id: i32) -> Result<Pod> {
     use schema::pods::dsl::*;
-    let pod: Pod = pods
-        .find(pod_id)
+    pods.find(pod_id)
         .first(conn)
-        .unwrap_or_else(|_| panic!("aaaaa"));
-    return pod;
+        .chain_err(|| format!("failed to load pod {}", pod_id))
 }
 
-pub fn create_pod(conn: &mut SqliteConnection, title: &str, url: &str) -> usize {
+pub fn create_pod(conn: &mut SqliteConnection, title: &str, url: &str) -> Result<usize> {
     use schema::pods;
     let new_pod = NewPod { title, url };
 
     diesel::insert_into(pods::table)
         .values(&new_pod)
         .execute(conn)
-        .expect("error saving pod")
+        .chain_err(|| "error saving pod")
 }
 
-pub fn mark_pod_as_downloaded(conn: &mut SqliteConnection, pod_id: i32) {
+/// Unplayed, new and downloaded episode counts of every pod, keyed by pod id
+pub fn get_pod_stats(conn: &mut SqliteConnection) -> Result<HashMap<i32, PodStats>> {
+    let stats = diesel::sql_query(
+        "SELECT pod_id, \
+         SUM(CASE WHEN played = 0 THEN 1 ELSE 0 END) AS unplayed, \
+         SUM(CASE WHEN is_new = 1 THEN 1 ELSE 0 END) AS new_episodes, \
+         SUM(CASE WHEN downloaded = 1 THEN 1 ELSE 0 END) AS downloaded \
+         FROM episodes GROUP BY pod_id",
+    )
+    .load::<PodStats>(conn)
+    .chain_err(|| "failed to load pod stats")?;
+    Ok(stats.into_iter().map(|stat| (stat.pod_id, stat)).collect())
+}
+
+pub fn mark_pod_as_refreshed(conn: &mut SqliteConnection, pod_id: i32, ts: i32) -> Result<()> {
+    use schema::pods;
+    diesel::update(pods::table.find(pod_id))
+        .set(pods::last_refreshed.eq(ts))
+        .execute(conn)
+        .chain_err(|| format!("failed to save refresh time of pod {}", pod_id))?;
+    Ok(())
+}
+
+/// Episodes stop counting as new once their pod has been looked at
+pub fn clear_new_episodes(conn: &mut SqliteConnection, pod_id_x: i32) -> Result<()> {
+    use schema::episodes::dsl::*;
+    diesel::update(episodes.filter(pod_id.eq(pod_id_x)).filter(is_new.eq(true)))
+        .set(is_new.eq(false))
+        .execute(conn)
+        .chain_err(|| "failed to clear new episodes")?;
+    Ok(())
+}
+
+pub fn mark_pod_as_downloaded(conn: &mut SqliteConnection, pod_id: i32) -> Result<()> {
     use schema::pods;
     use schema::pods::dsl::*;
-    let _ = diesel::update(pods.find(pod_id))
+    diesel::update(pods.find(pod_id))
         .set(pods::downloaded.eq(true))
-        .execute(conn);
+        .execute(conn)?;
+    Ok(())
 }
 
-pub fn create_episode(
-    conn: &mut SqliteConnection,
-    uid: &str,
-    pod_id: i32,
-    title: &str,
-    url: &str,
-    audio_url: &str,
-    description: &str,
-    pub_timestamp: i32,
-    downloaded: bool,
-) -> usize {
+/// Upserts a whole feed in one transaction. Episodes already known by uid get the
+/// publisher's metadata but keep their play state, new flag and downloaded file; if the
+/// enclosure moved after we downloaded it, the episode is flagged with `audio_changed`.
+/// SQLite can't combine multi-row inserts with `ON CONFLICT` in diesel, but a single
+/// transaction makes the per-row statements cheap.
+pub fn create_episodes(conn: &mut SqliteConnection, new_episodes: &[NewEpisode]) -> Result<usize> {
+    use diesel::upsert::excluded;
     use schema::episodes;
-    let new_episode = NewEpisode {
-        uid,
-        pod_id,
-        title,
-        url,
-        audio_url,
-        description,
-        downloaded,
-        audio_filepath: None,
-        played: false,
-        timestamp: 0.0,
-        pub_timestamp,
-        duration: None,
-    };
+    use schema::episodes::dsl::*;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let mut upserted = 0;
+        for new_episode in new_episodes {
+            upserted += diesel::insert_into(episodes::table)
+                .values(new_episode)
+                .on_conflict((pod_id, uid))
+                .do_update()
+                .set((
+                    title.eq(excluded(title)),
+                    url.eq(excluded(url)),
+                    description.eq(excluded(description)),
+                    pub_timestamp.eq(excluded(pub_timestamp)),
+                    audio_changed
+                        .eq(audio_changed.or(downloaded.and(audio_url.ne(excluded(audio_url))))),
+                    audio_url.eq(excluded(audio_url)),
+                    season.eq(excluded(season)),
+                    episode_number.eq(excluded(episode_number)),
+                ))
+                .execute(conn)?;
+        }
+        Ok(upserted)
+    })
+    .chain_err(|| "error saving episodes")
+}
 
-    diesel::insert_into(episodes::table)
-        .values(&new_episode)
-        .execute(conn)
-        .expect("error saving episode")
+pub fn get_episodes_for_pod(
+    conn: &mut SqliteConnection,
+    pod_id_x: i32,
+    sort: EpisodeSort,
+) -> Result<Vec<Episode>> {
+    use schema::episodes::dsl::*;
+    let query = episodes.filter(pod_id.eq(pod_id_x));
+    let result = match sort {
+        EpisodeSort::DateDesc => query.order(pub_timestamp.desc()).load::<Episode>(conn),
+        EpisodeSort::DateAsc => query.order(pub_timestamp.asc()).load::<Episode>(conn),
+        EpisodeSort::Duration => query
+            .order((duration.desc(), pub_timestamp.desc()))
+            .load::<Episode>(conn),
+        EpisodeSort::EpisodeNumber => query
+            // Trailers and bonus episodes often lack numbers, they go after the numbered ones
+            .order((
+                season.is_null(),
+                season.asc(),
+                episode_number.is_null(),
+                episode_number.asc(),
+                pub_timestamp.asc(),
+            ))
+            .load::<Episode>(conn),
+    };
+    result.chain_err(|| "failed to fetch episodes")
 }
 
-pub fn get_episodes_for_pod(conn: &mut SqliteConnection, pod_id_x: i32) -> Vec<Episode> {
+/// The episode of the same pod published right after `episode`, if any
+pub fn get_next_episode(conn: &mut SqliteConnection, episode: &Episode) -> Result<Option<Episode>> {
     use schema::episodes::dsl::*;
-    let eps = episodes
-        .filter(pod_id.eq(pod_id_x))
-        .order(pub_timestamp.desc())
-        .load::<Episode>(conn)
-        .expect("failed to fetch episodes");
-    return eps;
+    episodes
+        .filter(pod_id.eq(episode.pod_id))
+        .filter(
+            pub_timestamp.gt(episode.pub_timestamp).or(pub_timestamp
+                .eq(episode.pub_timestamp)
+                .and(id.gt(episode.id))),
+        )
+        .order((pub_timestamp.asc(), id.asc()))
+        .first::<Episode>(conn)
+        .optional()
+        .chain_err(|| "failed to find next episode")
 }
 
-pub fn get_episode(conn: &mut SqliteConnection, ep_id: i32) -> Episode {
+pub fn get_episode(conn: &mut SqliteConnection, ep_id: i32) -> Result<Episode> {
     use schema::episodes::dsl::*;
-    let ep: Episode = episodes
+    episodes
         .find(ep_id)
         .first(conn)
-        .unwrap_or_else(|_| panic!("aaaaa"));
-    return ep;
+        .chain_err(|| format!("failed to load episode {}", ep_id))
 }
 
 pub fn mark_episode_as_downloaded(
@@ -101,38 +290,246 @@ pub fn mark_episode_as_downloaded(
     episode: &Episode,
     filepath: &String,
     ep_duration: i32,
-) -> Episode {
+) -> Result<Episode> {
     use schema::episodes;
     use schema::episodes::dsl::*;
-    let _ = diesel::update(episodes.find(episode.id))
+    diesel::update(episodes.find(episode.id))
         .set((
             episodes::downloaded.eq(true),
+            episodes::audio_changed.eq(false),
             episodes::audio_filepath.eq(filepath),
             episodes::duration.eq(ep_duration),
+            // New audio needs its own analysis
+            episodes::loudness_gain.eq(None::<f32>),
+            episodes::silences.eq(None::<String>),
+        ))
+        .execute(conn)?;
+    get_episode(conn, episode.id)
+}
+
+pub fn set_audio_analysis(
+    conn: &mut SqliteConnection,
+    episode_id: i32,
+    gain: Option<f32>,
+    silences_json: &str,
+) -> Result<Episode> {
+    use schema::episodes;
+    use schema::episodes::dsl::*;
+    diesel::update(episodes.find(episode_id))
+        .set((
+            episodes::loudness_gain.eq(gain),
+            episodes::silences.eq(silences_json),
         ))
-        .execute(conn);
-    let updated_ep: Episode = episodes
-        .find(episode.id)
+        .execute(conn)
+        .chain_err(|| format!("failed to save audio analysis of episode {}", episode_id))?;
+    get_episode(conn, episode_id)
+}
+
+/// Adds `secs` to the time silence skipping saved on an episode
+pub fn add_time_saved(conn: &mut SqliteConnection, episode_id: i32, secs: f32) -> Result<Episode> {
+    use schema::episodes;
+    use schema::episodes::dsl::*;
+    diesel::update(episodes.find(episode_id))
+        .set(episodes::time_saved.eq(episodes::time_saved + secs))
+        .execute(conn)
+        .chain_err(|| format!("failed to save time saved on episode {}", episode_id))?;
+    get_episode(conn, episode_id)
+}
+
+pub fn get_total_time_saved(conn: &mut SqliteConnection) -> Result<f32> {
+    use schema::episodes::dsl::*;
+    let total: Option<f32> = episodes
+        .select(diesel::dsl::sum(time_saved))
         .first(conn)
-        .unwrap_or_else(|_| panic!("aaaaa"));
-    return updated_ep;
+        .chain_err(|| "failed to load time saved")?;
+    Ok(total.unwrap_or(0.0))
 }
 
-pub fn set_timestamp_on_episode(conn: &mut SqliteConnection, episode_id: i32, ts: f32) -> Episode {
+pub fn set_timestamp_on_episode(
+    conn: &mut SqliteConnection,
+    episode_id: i32,
+    ts: f32,
+) -> Result<Episode> {
     use schema::episodes;
     use schema::episodes::dsl::*;
-    let _ = diesel::update(episodes.find(episode_id))
+    diesel::update(episodes.find(episode_id))
         .set(episodes::timestamp.eq(ts))
-        .execute(conn);
-    return episodes
-        .find(episode_id)
-        .first(conn)
-        .unwrap_or_else(|_| panic!("aaaaa"));
+        .execute(conn)?;
+    get_episode(conn, episode_id)
+}
+
+pub fn set_played_on_episode(
+    conn: &mut SqliteConnection,
+    episode_id: i32,
+    is_played: bool,
+) -> Result<Episode> {
+    use schema::episodes;
+    use schema::episodes::dsl::*;
+    diesel::update(episodes.find(episode_id))
+        .set(episodes::played.eq(is_played))
+        .execute(conn)
+        .chain_err(|| format!("failed to mark episode {} as played", episode_id))?;
+    get_episode(conn, episode_id)
+}
+
+/// Marks every episode of the pod published before `before` as played
+pub fn mark_older_as_played(
+    conn: &mut SqliteConnection,
+    pod_id_x: i32,
+    before: i32,
+) -> Result<usize> {
+    use schema::episodes::dsl::*;
+    diesel::update(
+        episodes
+            .filter(pod_id.eq(pod_id_x))
+            .filter(pub_timestamp.lt(before)),
+    )
+    .set(played.eq(true))
+    .execute(conn)
+    .chain_err(|| "failed to mark older episodes as played")
+}
+
+/// Unplayed episodes of every pod published since `since`, newest first,
+/// leaving out the ones dismissed from the inbox
+pub fn get_inbox(conn: &mut SqliteConnection, since: i32) -> Result<Vec<Episode>> {
+    use schema::episodes::dsl::*;
+    episodes
+        .filter(played.eq(false))
+        .filter(dismissed.eq(false))
+        .filter(pub_timestamp.ge(since))
+        .order(pub_timestamp.desc())
+        .limit(500)
+        .load::<Episode>(conn)
+        .chain_err(|| "failed to load inbox")
 }
 
-pub fn delete_pod(conn: &mut SqliteConnection, pod_id_to_delete: i32) {
+pub fn dismiss_episode(conn: &mut SqliteConnection, episode_id: i32) -> Result<Episode> {
+    use schema::episodes;
     use schema::episodes::dsl::*;
+    diesel::update(episodes.find(episode_id))
+        .set(episodes::dismissed.eq(true))
+        .execute(conn)
+        .chain_err(|| format!("failed to dismiss episode {}", episode_id))?;
+    get_episode(conn, episode_id)
+}
+
+/// The play queue, next up first
+pub fn get_queue(conn: &mut SqliteConnection) -> Result<Vec<Episode>> {
+    use schema::{episodes, queue};
+    queue::table
+        .inner_join(episodes::table)
+        .order(queue::position.asc())
+        .select(episodes::all_columns)
+        .load::<Episode>(conn)
+        .chain_err(|| "failed to load queue")
+}
+
+/// Appends an episode to the end of the queue, episodes already queued keep their place
+pub fn enqueue_episode(conn: &mut SqliteConnection, ep_id: i32) -> Result<()> {
+    use schema::queue::dsl::*;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let last = queue
+            .select(diesel::dsl::max(position))
+            .first::<Option<i32>>(conn)?;
+        diesel::insert_into(queue)
+            .values((episode_id.eq(ep_id), position.eq(last.unwrap_or(0) + 1)))
+            .on_conflict(episode_id)
+            .do_nothing()
+            .execute(conn)?;
+        Ok(())
+    })
+    .chain_err(|| format!("failed to queue episode {}", ep_id))
+}
+
+pub fn remove_from_queue(conn: &mut SqliteConnection, ep_id: i32) -> Result<()> {
+    use schema::queue::dsl::*;
+    diesel::delete(queue.find(ep_id))
+        .execute(conn)
+        .chain_err(|| format!("failed to remove episode {} from queue", ep_id))?;
+    Ok(())
+}
+
+/// Swaps an episode with its neighbour, towards the front of the queue when `up`
+pub fn move_in_queue(conn: &mut SqliteConnection, ep_id: i32, up: bool) -> Result<()> {
+    use schema::queue::dsl::*;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let own = queue.find(ep_id).select(position).first::<i32>(conn)?;
+        let neighbour = if up {
+            queue
+                .filter(position.lt(own))
+                .order(position.desc())
+                .select((episode_id, position))
+                .first::<(i32, i32)>(conn)
+                .optional()?
+        } else {
+            queue
+                .filter(position.gt(own))
+                .order(position.asc())
+                .select((episode_id, position))
+                .first::<(i32, i32)>(conn)
+                .optional()?
+        };
+        if let Some((other_id, other_position)) = neighbour {
+            diesel::update(queue.find(other_id))
+                .set(position.eq(own))
+                .execute(conn)?;
+            diesel::update(queue.find(ep_id))
+                .set(position.eq(other_position))
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+    .chain_err(|| "failed to reorder queue")
+}
+
+/// Episodes go with the pod through `ON DELETE CASCADE`
+pub fn delete_pod(conn: &mut SqliteConnection, pod_id_to_delete: i32) -> Result<()> {
     use schema::pods::dsl::*;
-    let _ = diesel::delete(episodes.filter(pod_id.eq(pod_id_to_delete))).execute(conn);
-    let _ = diesel::delete(pods.find(pod_id_to_delete)).execute(conn);
+    diesel::delete(pods.find(pod_id_to_delete))
+        .execute(conn)
+        .chain_err(|| format!("failed to delete pod {}", pod_id_to_delete))?;
+    Ok(())
+}
+
+/// Full-text search over titles and show notes of every pod, best matches first.
+/// Each word of `query` is matched as a prefix, so "rust asy" finds "Rust async".
+pub fn search_episodes(conn: &mut SqliteConnection, query: &str) -> Result<Vec<Episode>> {
+    use diesel::sql_types::Text;
+    let fts_query = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(" ");
+    if fts_query.is_empty() {
+        return Ok(Vec::new());
+    }
+    diesel::sql_query(
+        "SELECT episodes.* FROM episodes_fts \
+         JOIN episodes ON episodes.id = episodes_fts.rowid \
+         WHERE episodes_fts MATCH ? ORDER BY rank LIMIT 200",
+    )
+    .bind::<Text, _>(fts_query)
+    .load::<Episode>(conn)
+    .chain_err(|| "failed to search episodes")
+}
+
+/// Points downloaded episodes at a moved data directory
+pub fn relocate_audio_files(
+    conn: &mut SqliteConnection,
+    old_dir: &str,
+    new_dir: &str,
+) -> Result<usize> {
+    use diesel::sql_types::{Integer, Text};
+    // Keeps the separator, so "./data/a.mp3" becomes "<new_dir>/a.mp3"
+    let old_prefix = format!("{}/", old_dir);
+    diesel::sql_query(
+        "UPDATE episodes SET audio_filepath = ? || substr(audio_filepath, ?) \
+         WHERE substr(audio_filepath, 1, ?) = ?",
+    )
+    .bind::<Text, _>(new_dir)
+    .bind::<Integer, _>(old_dir.len() as i32 + 1)
+    .bind::<Integer, _>(old_prefix.len() as i32)
+    .bind::<Text, _>(&old_prefix)
+    .execute(conn)
+    .chain_err(|| "failed to relocate audio files")
 }
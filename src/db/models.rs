@@ -8,6 +8,16 @@ pub struct Pod {
     pub title: String,
     pub url: String,
     pub downloaded: bool,
+    pub episode_sort: String,
+    pub last_refreshed: Option<i32>,
+    pub continuous_play: bool,
+    pub playback_speed: f32,
+    // Seconds, overriding the configured skip intervals when set
+    pub skip_forward: Option<i32>,
+    pub skip_back: Option<i32>,
+    // Seconds skipped at the start of an episode and left out before its end
+    pub skip_intro: i32,
+    pub skip_outro: i32,
 }
 
 #[derive(Insertable)]
@@ -18,7 +28,7 @@ pub struct NewPod<'a> {
     pub url: &'a str,
 }
 
-#[derive(Clone, Queryable, Identifiable, Associations, PartialEq, Debug)]
+#[derive(Clone, Queryable, QueryableByName, Identifiable, Associations, PartialEq, Debug)]
 #[diesel(belongs_to(Pod))]
 #[diesel(table_name = crate::db::schema::episodes)]
 #[diesel(check_for_backend(diesel::sqlite::Sqlite))]
@@ -36,6 +46,15 @@ pub struct Episode {
     pub timestamp: f32,
     pub pub_timestamp: i32,
     pub duration: Option<i32>,
+    pub audio_changed: bool,
+    pub season: Option<i32>,
+    pub episode_number: Option<i32>,
+    pub dismissed: bool,
+    pub is_new: bool,
+    pub loudness_gain: Option<f32>,
+    // JSON list of the long silences found by the audio analysis
+    pub silences: Option<String>,
+    pub time_saved: f32,
 }
 
 #[derive(Insertable)]
@@ -54,4 +73,114 @@ pub struct NewEpisode<'a> {
     pub timestamp: f32,
     pub pub_timestamp: i32,
     pub duration: Option<i32>,
+    pub season: Option<i32>,
+    pub episode_number: Option<i32>,
+    pub is_new: bool,
+}
+
+/// Episode counts shown next to a pod
+#[derive(QueryableByName, Clone, Copy, Default, Debug)]
+pub struct PodStats {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub pod_id: i32,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub unplayed: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub new_episodes: i64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub downloaded: i64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PodSort {
+    Added,
+    Title,
+    LatestEpisode,
+    UnplayedCount,
+}
+
+impl PodSort {
+    pub fn next(self) -> PodSort {
+        match self {
+            PodSort::Added => PodSort::Title,
+            PodSort::Title => PodSort::LatestEpisode,
+            PodSort::LatestEpisode => PodSort::UnplayedCount,
+            PodSort::UnplayedCount => PodSort::Added,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PodSort::Added => "added",
+            PodSort::Title => "title",
+            PodSort::LatestEpisode => "latest_episode",
+            PodSort::UnplayedCount => "unplayed_count",
+        }
+    }
+
+    /// Unknown values fall back to the order pods were added in, which is also the default
+    pub fn parse(value: &str) -> PodSort {
+        match value {
+            "title" => PodSort::Title,
+            "latest_episode" => PodSort::LatestEpisode,
+            "unplayed_count" => PodSort::UnplayedCount,
+            _ => PodSort::Added,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PodSort::Added => "added",
+            PodSort::Title => "title",
+            PodSort::LatestEpisode => "latest episode",
+            PodSort::UnplayedCount => "unplayed",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EpisodeSort {
+    DateDesc,
+    DateAsc,
+    Duration,
+    EpisodeNumber,
+}
+
+impl EpisodeSort {
+    pub fn next(self) -> EpisodeSort {
+        match self {
+            EpisodeSort::DateDesc => EpisodeSort::DateAsc,
+            EpisodeSort::DateAsc => EpisodeSort::Duration,
+            EpisodeSort::Duration => EpisodeSort::EpisodeNumber,
+            EpisodeSort::EpisodeNumber => EpisodeSort::DateDesc,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EpisodeSort::DateDesc => "date_desc",
+            EpisodeSort::DateAsc => "date_asc",
+            EpisodeSort::Duration => "duration",
+            EpisodeSort::EpisodeNumber => "episode_number",
+        }
+    }
+
+    /// Unknown values fall back to newest first, which is also the default
+    pub fn parse(value: &str) -> EpisodeSort {
+        match value {
+            "date_asc" => EpisodeSort::DateAsc,
+            "duration" => EpisodeSort::Duration,
+            "episode_number" => EpisodeSort::EpisodeNumber,
+            _ => EpisodeSort::DateDesc,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EpisodeSort::DateDesc => "newest",
+            EpisodeSort::DateAsc => "oldest",
+            EpisodeSort::Duration => "longest",
+            EpisodeSort::EpisodeNumber => "season/episode",
+        }
+    }
 }
@@ -15,6 +15,14 @@ diesel::table! {
         timestamp -> Float,
         pub_timestamp -> Integer,
         duration -> Nullable<Integer>,
+        audio_changed -> Bool,
+        season -> Nullable<Integer>,
+        episode_number -> Nullable<Integer>,
+        dismissed -> Bool,
+        is_new -> Bool,
+        loudness_gain -> Nullable<Float>,
+        silences -> Nullable<Text>,
+        time_saved -> Float,
     }
 }
 
@@ -24,10 +32,37 @@ diesel::table! {
         title -> Text,
         url -> Text,
         downloaded -> Bool,
+        episode_sort -> Text,
+        last_refreshed -> Nullable<Integer>,
+        continuous_play -> Bool,
+        playback_speed -> Float,
+        skip_forward -> Nullable<Integer>,
+        skip_back -> Nullable<Integer>,
+        skip_intro -> Integer,
+        skip_outro -> Integer,
     }
 }
 
+diesel::table! {
+    queue (episode_id) {
+        episode_id -> Integer,
+        position -> Integer,
+    }
+}
+
+diesel::table! {
+    settings (key) {
+        key -> Text,
+        value -> Text,
+    }
+}
+
+diesel::joinable!(episodes -> pods (pod_id));
+diesel::joinable!(queue -> episodes (episode_id));
+
 diesel::allow_tables_to_appear_in_same_query!(
     episodes,
     pods,
+    queue,
+    settings,
 );
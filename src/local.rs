@@ -0,0 +1,176 @@
+use crate::db::models::NewEpisode;
+
+use reqwest::Url;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::Hint;
+
+const AUDIO_EXTENSIONS: [&str; 10] = [
+    "mp3", "m4a", "m4b", "mp4", "aac", "flac", "wav", "ogg", "oga", "mka",
+];
+
+pub struct AudioMetadata {
+    pub duration: u64,
+    pub title: Option<String>,
+    pub comment: Option<String>,
+    pub track_number: Option<i32>,
+}
+
+/// An audio file in a folder podcast, ready to be stored as an episode
+pub struct LocalEpisode {
+    pub uid: String,
+    pub title: String,
+    pub url: String,
+    pub audio_filepath: String,
+    pub description: String,
+    pub pub_timestamp: i32,
+    pub duration: i32,
+    pub track_number: Option<i32>,
+}
+
+impl LocalEpisode {
+    pub fn as_new_episode(&self, pod_id: i32, is_new: bool) -> NewEpisode<'_> {
+        NewEpisode {
+            uid: &self.uid,
+            pod_id,
+            title: &self.title,
+            url: &self.url,
+            audio_url: &self.url,
+            description: &self.description,
+            audio_filepath: Some(&self.audio_filepath),
+            downloaded: true,
+            played: false,
+            timestamp: 0.0,
+            pub_timestamp: self.pub_timestamp,
+            duration: Some(self.duration),
+            season: None,
+            episode_number: self.track_number,
+            is_new,
+        }
+    }
+}
+
+pub fn is_file_url(url: &str) -> bool {
+    url.starts_with("file://")
+}
+
+pub fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    Url::parse(url).ok()?.to_file_path().ok()
+}
+
+/// Every audio file directly inside `dir` becomes an episode, with the file path as its uid
+pub fn scan_folder(dir: &Path) -> io::Result<Vec<LocalEpisode>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && is_audio_file(path))
+        .collect();
+    paths.sort();
+
+    let mut local_episodes = Vec::new();
+    for path in paths {
+        let metadata = match read_audio_metadata(&path) {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+        let filepath = path.to_string_lossy().into_owned();
+        let url = Url::from_file_path(&path)
+            .map(|u| u.to_string())
+            .unwrap_or_else(|_| format!("file://{}", filepath));
+        let title = metadata.title.unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| filepath.clone())
+        });
+        let pub_timestamp = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i32)
+            .unwrap_or(0);
+        local_episodes.push(LocalEpisode {
+            uid: filepath.clone(),
+            title,
+            url,
+            audio_filepath: filepath,
+            description: metadata.comment.unwrap_or_default(),
+            pub_timestamp,
+            duration: metadata.duration as i32,
+            track_number: metadata.track_number,
+        });
+    }
+    Ok(local_episodes)
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+pub fn read_audio_metadata(path: &Path) -> Option<AudioMetadata> {
+    let mut hint = Hint::new();
+    if let Some(extension_str) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension_str);
+    }
+    let source = Box::new(File::open(path).ok()?);
+    let mss = MediaSourceStream::new(source, Default::default());
+    let metadata_opts: MetadataOptions = Default::default();
+    let format_opts = FormatOptions {
+        enable_gapless: false,
+        ..Default::default()
+    };
+    let mut probed = symphonia::default::get_probe()
+        .format(&hint, mss, &format_opts, &metadata_opts)
+        .ok()?;
+
+    let params = &probed.format.default_track()?.codec_params;
+    let duration = match (params.n_frames, params.time_base) {
+        (Some(n_frames), Some(tb)) => tb.calc_time(n_frames).seconds,
+        _ => 0,
+    };
+
+    let mut audio_metadata = AudioMetadata {
+        duration,
+        title: None,
+        comment: None,
+        track_number: None,
+    };
+    // Container tags (mp4, ogg) live on the format reader, ID3 tags are read during the probe
+    if let Some(revision) = probed.format.metadata().current() {
+        apply_tags(&mut audio_metadata, revision);
+    }
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            apply_tags(&mut audio_metadata, revision);
+        }
+    }
+    Some(audio_metadata)
+}
+
+fn apply_tags(audio_metadata: &mut AudioMetadata, revision: &MetadataRevision) {
+    for tag in revision.tags() {
+        match tag.std_key {
+            Some(StandardTagKey::TrackTitle) if audio_metadata.title.is_none() => {
+                audio_metadata.title = Some(tag.value.to_string());
+            }
+            Some(StandardTagKey::Comment) if audio_metadata.comment.is_none() => {
+                audio_metadata.comment = Some(tag.value.to_string());
+            }
+            Some(StandardTagKey::TrackNumber) if audio_metadata.track_number.is_none() => {
+                // Track numbers are often written as "3/12"
+                let value = tag.value.to_string();
+                audio_metadata.track_number =
+                    value.split('/').next().and_then(|n| n.trim().parse().ok());
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::f64::consts::PI;
+
+/// Loudness episodes are normalized to, the usual target for spoken word podcasts
+pub const TARGET_LUFS: f64 = -16.0;
+/// Keeps badly measured or near silent files from being blasted or muted
+const MAX_GAIN_DB: f64 = 12.0;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Gain in dB that brings audio measured at `loudness` LUFS to [`TARGET_LUFS`]
+pub fn gain_for(loudness: f64) -> f32 {
+    (TARGET_LUFS - loudness).clamp(-MAX_GAIN_DB, MAX_GAIN_DB) as f32
+}
+
+/// Biquad in direct form I
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// The two K-weighting stages, a high shelf for the head's acoustics and a
+/// high pass for low frequencies, with coefficients for any sample rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+        ..Default::default()
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+        ..Default::default()
+    };
+    [shelf, high_pass]
+}
+
+/// Integrated loudness as defined by EBU R128 / ITU-R BS.1770. Collects the mean
+/// square of 100ms steps, which overlap into the 400ms gating blocks of the standard.
+pub struct Meter {
+    filters: Vec<[Biquad; 2]>,
+    channel_weight: f64,
+    step_len: usize,
+    step_position: usize,
+    step_sum: f64,
+    steps: Vec<f64>,
+}
+
+impl Meter {
+    pub fn new(sample_rate: u32, channels: usize) -> Meter {
+        Meter {
+            filters: vec![k_weighting(sample_rate); channels],
+            // Mono plays on both speakers, so it is measured like dual mono
+            channel_weight: if channels == 1 { 2.0 } else { 1.0 },
+            step_len: (sample_rate as usize / 10).max(1),
+            step_position: 0,
+            step_sum: 0.0,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn add_frame(&mut self, frame: &[f32]) {
+        for ([shelf, high_pass], &sample) in self.filters.iter_mut().zip(frame) {
+            let weighted = high_pass.process(shelf.process(sample as f64));
+            self.step_sum += self.channel_weight * weighted * weighted;
+        }
+        self.step_position += 1;
+        if self.step_position == self.step_len {
+            self.steps.push(self.step_sum / self.step_len as f64);
+            self.step_position = 0;
+            self.step_sum = 0.0;
+        }
+    }
+
+    /// Loudness in LUFS, `None` for silence
+    pub fn integrated(&self) -> Option<f64> {
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(4)
+            .map(|steps| steps.iter().sum::<f64>() / 4.0)
+            .filter(|&power| loudness(power) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+        let relative_gate = loudness(mean(&blocks)) + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&power| loudness(power) > relative_gate)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(loudness(mean(&gated)))
+    }
+}
+
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    /// Feeds `secs` of a 1 kHz sine peaking at `dbfs` to every channel
+    fn add_sine(meter: &mut Meter, channels: usize, dbfs: f64, secs: f64) {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        for n in 0..(secs * RATE as f64) as usize {
+            let sample = amplitude * (2.0 * PI * 1000.0 * n as f64 / RATE as f64).sin();
+            meter.add_frame(&vec![sample as f32; channels]);
+        }
+    }
+
+    fn assert_near(loudness: Option<f64>, expected: f64) {
+        let loudness = loudness.expect("no loudness measured");
+        assert!(
+            (loudness - expected).abs() < 0.1,
+            "measured {loudness} LUFS, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn measures_a_reference_sine() {
+        let mut meter = Meter::new(RATE, 2);
+        add_sine(&mut meter, 2, -23.0, 5.0);
+        assert_near(meter.integrated(), -23.0);
+    }
+
+    #[test]
+    fn measures_mono_like_dual_mono() {
+        let mut meter = Meter::new(RATE, 1);
+        add_sine(&mut meter, 1, -23.0, 5.0);
+        assert_near(meter.integrated(), -23.0);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let mut meter = Meter::new(RATE, 2);
+        add_sine(&mut meter, 2, -200.0, 5.0);
+        assert_eq!(meter.integrated(), None);
+    }
+
+    #[test]
+    fn audio_below_the_absolute_gate_has_no_loudness() {
+        let mut meter = Meter::new(RATE, 2);
+        add_sine(&mut meter, 2, -75.0, 5.0);
+        assert_eq!(meter.integrated(), None);
+    }
+
+    #[test]
+    fn audio_shorter_than_a_block_has_no_loudness() {
+        let mut meter = Meter::new(RATE, 2);
+        add_sine(&mut meter, 2, -23.0, 0.3);
+        assert_eq!(meter.integrated(), None);
+    }
+
+    #[test]
+    fn quiet_parts_are_gated_out() {
+        let mut meter = Meter::new(RATE, 2);
+        add_sine(&mut meter, 2, -23.0, 10.0);
+        add_sine(&mut meter, 2, -50.0, 10.0);
+        add_sine(&mut meter, 2, -200.0, 10.0);
+        assert_near(meter.integrated(), -23.0);
+    }
+
+    #[test]
+    fn louder_parts_count_fully() {
+        let mut meter = Meter::new(RATE, 2);
+        add_sine(&mut meter, 2, -20.0, 10.0);
+        add_sine(&mut meter, 2, -26.0, 10.0);
+        // Both halves pass the gates, so their powers average
+        let expected = 10.0 * ((10f64.powf(-2.0) + 10f64.powf(-2.6)) / 2.0).log10();
+        assert_near(meter.integrated(), expected);
+    }
+
+    #[test]
+    fn gain_is_clamped() {
+        assert_eq!(gain_for(-23.0), 7.0);
+        assert_eq!(gain_for(-16.0), 0.0);
+        assert_eq!(gain_for(-60.0), 12.0);
+        assert_eq!(gain_for(0.0), -12.0);
+    }
+}
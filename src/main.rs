@@ -5,17 +5,26 @@ extern crate rss;
 extern crate serde;
 extern crate tui;
 
+mod analysis;
 mod app;
+mod config;
 mod db;
+mod local;
+mod loudness;
 mod network;
+mod pitch_shift;
 mod player;
+mod position;
 
 use app::{App, InputField, InputMode, NavigationStack};
-use db::{establish_connection, get_pods};
+use config::Config;
+use db::{establish_connection, relocate_audio_files};
 use player::Player;
+use position::PositionTracker;
 use unicode_width::UnicodeWidthStr;
 
 use crossterm::{
+    cursor::Show,
     event::{
         self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
     },
@@ -25,12 +34,16 @@ use crossterm::{
 use network::{IoEvent, Network};
 
 use crate::app::StatefulList;
-use crate::db::models::Pod;
+use crate::db::models::{Pod, PodStats};
+use chrono::{NaiveDateTime, Utc};
 use db::models::Episode;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::collections::HashMap;
 use std::error::Error;
 use std::{
+    any::Any,
     io,
+    panic::AssertUnwindSafe,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -47,6 +60,9 @@ use tui::{
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
+/// How often the position of a playing episode is written to the db
+const POSITION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
 fn run_migrations(
     connection: &mut impl MigrationHarness<diesel::sqlite::Sqlite>,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
@@ -61,29 +77,54 @@ fn run_migrations(
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Set up the db before entering raw mode so failures print to a usable terminal
+    let config = Config::load()?;
+    config.migrate_legacy_database()?;
+    let legacy_data_dir = config.migrate_legacy_data_dir()?;
+    let mut connection = establish_connection(&config.database_url())?;
+    run_migrations(&mut connection).map_err(|e| e as Box<dyn Error>)?;
+    // Also rewrites paths an earlier start failed to, before it got this far
+    if let Some((old_dir, new_dir)) = legacy_data_dir {
+        relocate_audio_files(&mut connection, &old_dir, &new_dir)?;
+    }
+
+    let positions = PositionTracker::default();
+    install_panic_hook(positions.clone(), config.database_url());
+    #[cfg(unix)]
+    tokio::spawn(save_on_signal(positions.clone(), config.database_url()));
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let mut connection = establish_connection();
-    run_migrations(&mut connection).unwrap();
-
-    let pods = get_pods(&mut connection);
-
     let player = Player::new();
 
     let tick_rate = Duration::from_millis(250);
     let (sync_io_tx, sync_io_rx) = std::sync::mpsc::channel::<IoEvent>();
-    let app = Arc::new(Mutex::new(App::new(sync_io_tx, player, pods)));
+    let app = Arc::new(Mutex::new(App::new(
+        sync_io_tx,
+        player,
+        connection,
+        config.inbox_days,
+        config.skip_intervals,
+        positions,
+    )));
 
+    // The network thread writes through its own db handle
+    let network_connection = establish_connection(&config.database_url())?;
     let cloned_app = Arc::clone(&app);
     std::thread::spawn(move || {
-        let mut network = Network::new(&app);
+        let mut network = Network::new(
+            &app,
+            network_connection,
+            config.database_url(),
+            config.data_dir,
+        );
         start_tokio(sync_io_rx, &mut network);
     });
-    let _res = run_app(&mut terminal, &cloned_app, tick_rate).await?;
+    run_app(&mut terminal, &cloned_app, tick_rate).await?;
 
     disable_raw_mode()?;
     execute!(
@@ -96,10 +137,75 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-#[tokio::main]
-async fn start_tokio<'a>(io_rx: std::sync::mpsc::Receiver<IoEvent>, network: &mut Network) {
+/// Leaves raw mode and the alternate screen, for when the app goes down
+/// without reaching the end of main
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        Show
+    );
+}
+
+/// Saves the last position and gives the terminal back before the panic message prints
+fn install_panic_hook(positions: PositionTracker, database_url: String) {
+    let default_hook = std::panic::take_hook();
+    let ui_thread = std::thread::current().id();
+    std::panic::set_hook(Box::new(move |info| {
+        // Only a panic of the ui takes the app down. The network loop reports its own
+        // panics in the ui, printing them would draw over the alternate screen.
+        if std::thread::current().id() != ui_thread {
+            return;
+        }
+        positions.save(&database_url);
+        restore_terminal();
+        default_hook(info);
+        std::process::exit(101);
+    }));
+}
+
+/// Saves the last position when the app is killed or its terminal closes
+#[cfg(unix)]
+async fn save_on_signal(positions: PositionTracker, database_url: String) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (mut terminate, mut hangup) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::hangup()),
+    ) {
+        (Ok(terminate), Ok(hangup)) => (terminate, hangup),
+        _ => return,
+    };
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = hangup.recv() => {}
+    }
+    positions.save(&database_url);
+    restore_terminal();
+    std::process::exit(0);
+}
+
+/// Handles network events one at a time. An event that panics is reported in the
+/// ui, the loop carries on with the next one.
+fn start_tokio(io_rx: std::sync::mpsc::Receiver<IoEvent>, network: &mut Network) {
+    let runtime = tokio::runtime::Runtime::new().expect("failed to build the network runtime");
     while let Ok(io_event) = io_rx.recv() {
-        network.handle_network_event(io_event).await;
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(network.handle_network_event(io_event))
+        }));
+        if let Err(panic) = result {
+            runtime.block_on(network.report_panic(panic_message(&*panic)));
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("unknown cause", String::as_str),
     }
 }
 
@@ -109,15 +215,18 @@ async fn run_app<B: Backend>(
     tick_rate: Duration,
 ) -> io::Result<()> {
     let mut last_tick = Instant::now();
+    let mut last_save = Instant::now();
     loop {
         let mut app = app.lock().await;
-        terminal.draw(|mut f| ui(&mut f, &mut app))?;
+        terminal.draw(|f| ui(f, &mut app))?;
 
         let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
         if crossterm::event::poll(timeout)? {
             let event = event::read()?;
+            // Errors stay on screen until the next key press
+            app.error_message = None;
             match app.input_mode {
                 InputMode::Normal => match event {
                     Event::Key(KeyEvent {
@@ -128,21 +237,66 @@ async fn run_app<B: Backend>(
                             app.save_timestamp();
                             return Ok(());
                         }
-                        NavigationStack::Episodes => app.back(),
+                        NavigationStack::Episodes
+                        | NavigationStack::Search
+                        | NavigationStack::Inbox
+                        | NavigationStack::Queue => app.back(),
                     },
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('j'),
+                    }) if app.show_description => app.description_next(true),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('k'),
+                    }) if app.show_description => app.description_next(false),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Enter,
+                    }) if app.has_timestamps() => app.seek_to_timestamp(),
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('j'),
                     }) => match app.navigation_stack {
                         NavigationStack::Main => app.pods.next(),
-                        NavigationStack::Episodes => app.episodes.as_mut().unwrap().next(),
+                        NavigationStack::Episodes => {
+                            if let Some(episodes) = app.episodes.as_mut() {
+                                episodes.next()
+                            }
+                        }
+                        NavigationStack::Search => {
+                            if let Some(results) = app.search_results.as_mut() {
+                                results.next()
+                            }
+                        }
+                        NavigationStack::Inbox => {
+                            if let Some(inbox) = app.inbox.as_mut() {
+                                inbox.next()
+                            }
+                        }
+                        NavigationStack::Queue => app.queue.next(),
                     },
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('k'),
                     }) => match app.navigation_stack {
                         NavigationStack::Main => app.pods.previous(),
-                        NavigationStack::Episodes => app.episodes.as_mut().unwrap().previous(),
+                        NavigationStack::Episodes => {
+                            if let Some(episodes) = app.episodes.as_mut() {
+                                episodes.previous()
+                            }
+                        }
+                        NavigationStack::Search => {
+                            if let Some(results) = app.search_results.as_mut() {
+                                results.previous()
+                            }
+                        }
+                        NavigationStack::Inbox => {
+                            if let Some(inbox) = app.inbox.as_mut() {
+                                inbox.previous()
+                            }
+                        }
+                        NavigationStack::Queue => app.queue.previous(),
                     },
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
@@ -150,6 +304,9 @@ async fn run_app<B: Backend>(
                     }) => match app.navigation_stack {
                         NavigationStack::Main => app.handle_enter_pod(),
                         NavigationStack::Episodes => app.handle_enter_episode(),
+                        NavigationStack::Search => app.handle_enter_search_result(),
+                        NavigationStack::Inbox => app.handle_enter_inbox_episode(),
+                        NavigationStack::Queue => app.handle_enter_queue_episode(),
                     },
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
@@ -162,11 +319,43 @@ async fn run_app<B: Backend>(
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('o'),
-                    }) => app.player.jump_forward_10s(),
+                    }) => app.skip(true, false),
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('i'),
-                    }) => app.player.jump_backward_10s(),
+                    }) => app.skip(false, false),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Right,
+                    }) => app.skip(true, true),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Left,
+                    }) => app.skip(false, true),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char('>'),
+                        ..
+                    }) => app.cycle_skip_interval(true),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char('<'),
+                        ..
+                    }) => app.cycle_skip_interval(false),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char('{'),
+                        ..
+                    }) => app.cycle_intro_outro(true),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char('}'),
+                        ..
+                    }) => app.cycle_intro_outro(false),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('G'),
+                    }) => app.start_goto(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char(c @ '0'..='9'),
+                    }) => app.player.seek_to_percent(c.to_digit(10).unwrap() * 10),
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('?'),
@@ -182,13 +371,173 @@ async fn run_app<B: Backend>(
                     Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('x'),
-                    }) => app.delete_pod_and_episodes(),
+                    }) => match app.navigation_stack {
+                        NavigationStack::Inbox => app.dismiss_inbox_episode(),
+                        NavigationStack::Queue => app.remove_selected_from_queue(),
+                        NavigationStack::Main => app.delete_pod_and_episodes(),
+                        NavigationStack::Episodes | NavigationStack::Search => {}
+                    },
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('I'),
+                    }) => app.open_inbox(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('g'),
+                    }) => app.download_selected_episode(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('Q'),
+                    }) => app.open_queue(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('a'),
+                    }) => app.queue_selected_episode(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('c'),
+                    }) => app.toggle_continuous_play(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char(']'),
+                    }) => app.change_speed(0.1),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('['),
+                    }) => app.change_speed(-0.1),
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Char('+') | KeyCode::Char('='),
+                        ..
+                    }) => app.change_volume(5),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('-'),
+                    }) => app.change_volume(-5),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('m'),
+                    }) => app.player.toggle_mute(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('l'),
+                    }) => app.toggle_loudness_normalization(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('S'),
+                    }) => app.toggle_skip_silence(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('z'),
+                    }) => app.cycle_sleep_timer(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('K'),
+                    }) => app.move_selected_in_queue(true),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('J'),
+                    }) => app.move_selected_in_queue(false),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('s'),
+                    }) => app.start_search(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('/'),
+                    }) => {
+                        if let NavigationStack::Episodes = app.navigation_stack {
+                            app.start_episode_query()
+                        }
+                    }
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('p'),
+                    }) => app.toggle_played(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::SHIFT,
+                        code: KeyCode::Char('P'),
+                    }) => app.mark_older_as_played(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('t'),
+                    }) => app.cycle_sort(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Char('f'),
+                    }) => {
+                        if let NavigationStack::Episodes = app.navigation_stack {
+                            app.cycle_episode_filter()
+                        }
+                    }
                     _ => {}
                 },
-                InputMode::Help => match event {
-                    Event::Key(KeyEvent {
+                InputMode::Help => {
+                    if let Event::Key(KeyEvent {
                         modifiers: KeyModifiers::NONE,
                         code: KeyCode::Char('q'),
+                    }) = event
+                    {
+                        app.input_mode = InputMode::Normal
+                    }
+                }
+                InputMode::Search => match event {
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        code: KeyCode::Char(c),
+                    }) => app.search_query.push(c),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Backspace,
+                    }) => {
+                        let _ = app.search_query.pop();
+                    }
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Esc,
+                    }) => app.cancel_search(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Enter,
+                    }) => app.search(),
+                    _ => {}
+                },
+                InputMode::GoTo => match event {
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        code: KeyCode::Char(c),
+                    }) => app.goto_input.push(c),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Backspace,
+                    }) => {
+                        let _ = app.goto_input.pop();
+                    }
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Esc,
+                    }) => app.input_mode = InputMode::Normal,
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Enter,
+                    }) => app.goto(),
+                    _ => {}
+                },
+                InputMode::Filter => match event {
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        code: KeyCode::Char(c),
+                    }) => app.push_episode_query(c),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Backspace,
+                    }) => app.pop_episode_query(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Esc,
+                    }) => app.clear_episode_query(),
+                    Event::Key(KeyEvent {
+                        modifiers: KeyModifiers::NONE,
+                        code: KeyCode::Enter,
                     }) => app.input_mode = InputMode::Normal,
                     _ => {}
                 },
@@ -238,18 +587,74 @@ async fn run_app<B: Backend>(
             }
         }
         if last_tick.elapsed() >= tick_rate {
+            app.on_tick();
             last_tick = Instant::now();
         }
+        if last_save.elapsed() >= POSITION_SAVE_INTERVAL {
+            app.save_playing_position();
+            last_save = Instant::now();
+        }
     }
 }
 
-fn render_pods<B: Backend>(f: &mut Frame<B>, pods: &StatefulList<Pod>, main_chunks: &[Rect]) {
+fn render_pods<B: Backend>(
+    f: &mut Frame<B>,
+    pods: &StatefulList<Pod>,
+    stats: &HashMap<i32, PodStats>,
+    title: String,
+    main_chunks: &[Rect],
+) {
+    let now = Utc::now().timestamp();
     let items: Vec<ListItem> = pods
         .items
         .iter()
         .map(|i| {
-            let lines = vec![Spans::from(i.title.clone())];
-            ListItem::new(lines).style(Style::default().fg(Color::White))
+            let stat = stats.get(&i.id).copied().unwrap_or_default();
+            let mut spans = vec![Span::from(i.title.clone())];
+            let badge = Style::default().fg(Color::Gray);
+            if stat.unplayed > 0 {
+                spans.push(Span::styled(format!("  {} unplayed", stat.unplayed), badge));
+            }
+            if stat.new_episodes > 0 {
+                spans.push(Span::styled(
+                    format!("  {} new", stat.new_episodes),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            if stat.downloaded > 0 {
+                spans.push(Span::styled(
+                    format!("  {} downloaded", stat.downloaded),
+                    badge,
+                ));
+            }
+            if i.continuous_play {
+                spans.push(Span::styled("  continuous", badge));
+            }
+            if i.skip_forward.is_some() || i.skip_back.is_some() {
+                let fmt_skip = |secs: Option<i32>| match secs {
+                    Some(secs) => format!("{}s", secs),
+                    None => String::from("default"),
+                };
+                spans.push(Span::styled(
+                    format!(
+                        "  skips -{} +{}",
+                        fmt_skip(i.skip_back),
+                        fmt_skip(i.skip_forward)
+                    ),
+                    badge,
+                ));
+            }
+            if i.skip_intro > 0 {
+                spans.push(Span::styled(format!("  intro {}s", i.skip_intro), badge));
+            }
+            if i.skip_outro > 0 {
+                spans.push(Span::styled(format!("  outro {}s", i.skip_outro), badge));
+            }
+            if let Some(last_refreshed) = i.last_refreshed {
+                let age = fmt_age(now - i64::from(last_refreshed));
+                spans.push(Span::styled(format!("  refreshed {}", age), badge));
+            }
+            ListItem::new(vec![Spans::from(spans)]).style(Style::default().fg(Color::White))
         })
         .collect();
 
@@ -260,7 +665,7 @@ fn render_pods<B: Backend>(f: &mut Frame<B>, pods: &StatefulList<Pod>, main_chun
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(active_border)
-                .title("Pods"),
+                .title(title),
         )
         .highlight_style(
             Style::default()
@@ -272,11 +677,29 @@ fn render_pods<B: Backend>(f: &mut Frame<B>, pods: &StatefulList<Pod>, main_chun
     f.render_stateful_widget(pods_items, main_chunks[0], &mut pods.state.clone());
 }
 
+/// Rough age like "5m ago" for the pod list
+fn fmt_age(secs: i64) -> String {
+    match secs {
+        s if s < 60 => String::from("just now"),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
+
+/// Short duration like "1h 5m", "3m 20s" or "12s"
+fn fmt_duration(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 60 * 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / (60 * 60), (s % (60 * 60)) / 60),
+    }
+}
+
 fn render_episodes<B: Backend>(
     f: &mut Frame<B>,
     episodes: &StatefulList<Episode>,
-    show_description: bool,
-    size: Rect,
+    title: String,
     main_chunks: &[Rect],
 ) {
     let mut episodes_items = Vec::<ListItem>::new();
@@ -291,11 +714,22 @@ fn render_episodes<B: Backend>(
                 _ => String::from("●"),
             }
         }
+        if ep.played {
+            icon = String::from("✓");
+        }
         let text = vec![Spans::from(format!("{} {}", icon, &ep.title))];
-        episodes_items.push(ListItem::new(text).style(match &ep.downloaded {
-            false => Style::default().fg(Color::White),
-            true => Style::default().fg(Color::Green),
-        }));
+        let mut style = match (&ep.downloaded, &ep.audio_changed) {
+            (false, _) => Style::default().fg(Color::White),
+            (true, true) => Style::default().fg(Color::Yellow),
+            (true, false) => Style::default().fg(Color::Green),
+        };
+        if ep.played {
+            style = style.add_modifier(Modifier::DIM);
+        }
+        if ep.is_new {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        episodes_items.push(ListItem::new(text).style(style));
     }
 
     let active_border = Style::default().fg(Color::White);
@@ -305,7 +739,7 @@ fn render_episodes<B: Backend>(
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(active_border)
-                .title("Episodes"),
+                .title(title),
         )
         .highlight_style(
             Style::default()
@@ -315,37 +749,174 @@ fn render_episodes<B: Backend>(
         .highlight_symbol(">> ");
 
     f.render_stateful_widget(episodes_list, main_chunks[0], &mut episodes.state.clone());
+}
 
-    if let Some(selected_index) = episodes.state.selected() {
-        let selected_ep = episodes.items.get(selected_index).unwrap();
-        if show_description {
-            let area = centered_rect(50, 50, size);
-            let text = vec![Spans::from(Span::from(selected_ep.description.clone()))];
-            let para = Paragraph::new(text)
-                .block(
-                    Block::default()
-                        .title("Episode description")
-                        .borders(Borders::ALL),
-                )
-                .style(Style::default().fg(Color::White))
-                .wrap(Wrap { trim: true });
-            f.render_widget(Clear, area);
-            f.render_widget(para, area);
+/// Show notes of the highlighted episode, with the times they mention listed below
+fn render_description<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) {
+    if let Some(selected_ep) = app.selected_episode() {
+        let area = centered_rect(50, 50, size);
+        f.render_widget(Clear, area);
+        let timestamps = &app.timestamps;
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Min(3),
+                    match timestamps.items.is_empty() {
+                        true => Constraint::Length(0),
+                        false => Constraint::Percentage(40),
+                    },
+                ]
+                .as_ref(),
+            )
+            .split(area);
+        let text = vec![Spans::from(Span::from(selected_ep.description.clone()))];
+        let para = Paragraph::new(text)
+            .block(
+                Block::default()
+                    .title("Episode description")
+                    .borders(Borders::ALL),
+            )
+            .style(Style::default().fg(Color::White))
+            .wrap(Wrap { trim: true });
+        f.render_widget(para, chunks[0]);
+        if timestamps.items.is_empty() {
+            return;
         }
+        let items: Vec<ListItem> = timestamps
+            .items
+            .iter()
+            .map(|timestamp| ListItem::new(timestamp.line.clone()))
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title("Timestamps (Enter to jump)")
+                    .borders(Borders::ALL),
+            )
+            .style(Style::default().fg(Color::White))
+            .highlight_style(
+                Style::default()
+                    .bg(Color::DarkGray)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(">> ");
+        f.render_stateful_widget(list, chunks[1], &mut timestamps.state.clone());
     }
 }
 
+/// Episodes from several pods, each prefixed with its pod and optionally its publish date
+fn render_episode_results<B: Backend>(
+    f: &mut Frame<B>,
+    app: &App,
+    results: &StatefulList<Episode>,
+    title: String,
+    show_date: bool,
+    main_chunks: &[Rect],
+) {
+    let items: Vec<ListItem> = results
+        .items
+        .iter()
+        .map(|ep| {
+            let mut spans = Vec::new();
+            if show_date {
+                let date = NaiveDateTime::from_timestamp_opt(i64::from(ep.pub_timestamp), 0)
+                    .map(|dt| dt.format("%Y-%m-%d ").to_string())
+                    .unwrap_or_default();
+                spans.push(Span::styled(date, Style::default().fg(Color::Gray)));
+            }
+            spans.push(Span::styled(
+                format!("{}: ", app.pod_title(ep.pod_id)),
+                Style::default().fg(Color::Gray),
+            ));
+            spans.push(Span::from(ep.title.clone()));
+            let lines = vec![Spans::from(spans)];
+            let style = match &ep.downloaded {
+                false => Style::default().fg(Color::White),
+                true => Style::default().fg(Color::Green),
+            };
+            ListItem::new(lines).style(match ep.played {
+                true => style.add_modifier(Modifier::DIM),
+                false => style,
+            })
+        })
+        .collect();
+
+    let active_border = Style::default().fg(Color::White);
+
+    let results_list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(active_border)
+                .title(title),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    f.render_stateful_widget(results_list, main_chunks[0], &mut results.state.clone());
+}
+
+/// One line text prompt in the middle of the screen
+fn render_line_input<B: Backend>(f: &mut Frame<B>, text: &str, title: &str, size: Rect) {
+    let area = centered_rect(80, 20, size);
+    let input_width = area.width;
+    let mut scroll_offset = 0;
+    if text.width() as u16 >= input_width - 2 {
+        scroll_offset = text.width() as u16 - (input_width - 2);
+    }
+    let input = Paragraph::new(text)
+        .style(Style::default())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .scroll((0, scroll_offset));
+    let mut cursor_pos = text.width() as u16 + 1;
+    if cursor_pos >= input_width - 2 {
+        cursor_pos = input_width - 2;
+    }
+    f.set_cursor(area.x + cursor_pos, area.y + 1);
+    f.render_widget(Clear, area);
+    f.render_widget(input, area);
+}
+
 fn render_player<B: Backend>(f: &mut Frame<B>, app: &mut App, main_chunks: &[Rect]) {
     let progress = app.player.get_progress();
     let mut player_spans: Vec<Spans> = Vec::new();
     let mut player_title = String::from("Player");
-    match &app.player.selected_track {
-        Some(track) => {
-            player_spans.push(Spans::from(Span::from(progress.clone())));
-            player_title = track.title.clone();
-        }
-        None => {}
+    let mut volume = match app.player.is_muted() {
+        true => String::from("muted"),
+        false => format!("vol {}%", app.player.volume()),
     };
+    if app.player.normalizes_loudness() {
+        volume.push_str(" (leveled)");
+    }
+    if app.player.skips_silence() {
+        volume.push_str("  skipping silence");
+    }
+    if let Some(sleep_timer) = app.sleep_timer_label() {
+        volume = format!("{}  {}", volume, sleep_timer);
+    }
+    if let Some(track) = &app.player.selected_track {
+        player_spans.push(Spans::from(Span::from(format!(
+            "{}  {:.2}x  {}",
+            progress,
+            app.player.speed(),
+            volume
+        ))));
+        if track.time_saved > 0.0 || app.total_time_saved > 0.0 {
+            player_spans.push(Spans::from(Span::from(format!(
+                "Silence skipped: {} this episode, {} in total",
+                fmt_duration(track.time_saved as u64),
+                fmt_duration(app.total_time_saved as u64)
+            ))));
+        }
+        player_title = track.title.clone();
+    } else {
+        player_spans.push(Spans::from(Span::from(volume)));
+    }
     if app.is_downloading {
         player_spans.push(Spans::from(Span::from("Episode is downloading...")));
     }
@@ -354,6 +925,12 @@ fn render_player<B: Backend>(f: &mut Frame<B>, app: &mut App, main_chunks: &[Rec
             "Checking and fetching new episodes...",
         )));
     }
+    if let Some(error_message) = &app.error_message {
+        player_spans.push(Spans::from(Span::styled(
+            format!("Error: {}", error_message),
+            Style::default().fg(Color::Red),
+        )));
+    }
     let player = Paragraph::new(player_spans)
         .block(Block::default().title(player_title).borders(Borders::ALL))
         .style(Style::default().fg(Color::White));
@@ -419,10 +996,51 @@ fn render_help<B: Backend>(f: &mut Frame<B>, size: Rect) {
         Spans::from(Span::from("N to create a new pod")),
         Spans::from(Span::from("Space to toggle play/pause")),
         Spans::from(Span::from("R to refresh a podcasts feed/episodes")),
-        Spans::from(Span::from("O to seek 100s ahead")),
-        Spans::from(Span::from("I to seek 10s back")),
-        Spans::from(Span::from("D to view episode description")),
-        Spans::from(Span::from("X to delete pod and episodes")),
+        Spans::from(Span::from(
+            "O to skip ahead, I to skip back (10s by default)",
+        )),
+        Spans::from(Span::from(
+            "Right and Left to skip further (60s by default)",
+        )),
+        Spans::from(Span::from("< and > to cycle a pod's own skip intervals")),
+        Spans::from(Span::from(
+            "D to view episode description, J/K and Enter to jump to a time it mentions",
+        )),
+        Spans::from(Span::from(
+            "X in the pod list to delete a pod and its episodes",
+        )),
+        Spans::from(Span::from("S to search all episodes")),
+        Spans::from(Span::from("/ to search episode titles, Esc to clear")),
+        Spans::from(Span::from("F to cycle episode filters")),
+        Spans::from(Span::from("T to cycle sort order of pods or episodes")),
+        Spans::from(Span::from(
+            "P to toggle played, Shift+P to mark older as played",
+        )),
+        Spans::from(Span::from(
+            "Shift+I to open the inbox, X to dismiss from it",
+        )),
+        Spans::from(Span::from("G to download an episode without playing it")),
+        Spans::from(Span::from(
+            "A to add an episode to the queue, Shift+Q to open it",
+        )),
+        Spans::from(Span::from(
+            "In the queue: X to remove, Shift+J/K to move down/up",
+        )),
+        Spans::from(Span::from("C to toggle continuous play of a pod")),
+        Spans::from(Span::from("[ and ] to slow down or speed up playback")),
+        Spans::from(Span::from("- and + to change the volume, M to mute")),
+        Spans::from(Span::from("L to toggle loudness leveling")),
+        Spans::from(Span::from("Shift+S to toggle skipping long silences")),
+        Spans::from(Span::from(
+            "Shift+G to go to a time (hh:mm:ss, mm:ss or NN%)",
+        )),
+        Spans::from(Span::from("0 to 9 to jump to 0% to 90% of the episode")),
+        Spans::from(Span::from(
+            "Z to cycle the sleep timer (15 to 90 minutes, end of chapter, end of episode, off)",
+        )),
+        Spans::from(Span::from(
+            "{ and } to cycle the intro skipped and the outro left out for a pod",
+        )),
     ];
     let para = Paragraph::new(text)
         .block(Block::default().title("Help").borders(Borders::ALL))
@@ -441,15 +1059,36 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
 
     match &app.navigation_stack {
         NavigationStack::Main => {
-            render_pods(f, &app.pods, &main_chunks);
+            let title = format!("Pods ({})", app.pod_sort.label());
+            render_pods(f, &app.pods, &app.pod_stats, title, &main_chunks);
         }
         NavigationStack::Episodes => {
             if let Some(episodes) = &app.episodes {
-                render_episodes(f, episodes, app.show_description, size, &main_chunks);
+                let title = app.episodes_title();
+                render_episodes(f, episodes, title, &main_chunks);
+            }
+        }
+        NavigationStack::Search => {
+            if let Some(results) = &app.search_results {
+                let title = format!("Search: {}", app.search_query);
+                render_episode_results(f, app, results, title, false, &main_chunks);
             }
         }
+        NavigationStack::Inbox => {
+            if let Some(inbox) = &app.inbox {
+                let title = app.inbox_title();
+                render_episode_results(f, app, inbox, title, true, &main_chunks);
+            }
+        }
+        NavigationStack::Queue => {
+            let title = String::from("Queue");
+            render_episode_results(f, app, &app.queue, title, false, &main_chunks);
+        }
     }
 
+    if app.show_description {
+        render_description(f, app, size);
+    }
     render_player(f, app, &main_chunks);
 
     if let InputMode::Editing = app.input_mode {
@@ -458,6 +1097,12 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App) {
     if let InputMode::Help = app.input_mode {
         render_help(f, size);
     }
+    if let InputMode::Search = app.input_mode {
+        render_line_input(f, &app.search_query, "Search all episodes", size);
+    }
+    if let InputMode::GoTo = app.input_mode {
+        render_line_input(f, &app.goto_input, "Go to (hh:mm:ss, mm:ss or NN%)", size);
+    }
 }
 
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
@@ -1,219 +1,298 @@
 extern crate rss;
+use crate::analysis::analyse;
 use crate::app::App;
-use crate::db::models::{Episode, Pod};
+use crate::db::models::{Episode, NewEpisode, Pod};
 use crate::db::{
-    create_episode, establish_connection, get_episodes_for_pod, mark_episode_as_downloaded,
-    mark_pod_as_downloaded,
+    create_episodes, establish_connection, mark_episode_as_downloaded, mark_pod_as_downloaded,
+    mark_pod_as_refreshed, set_audio_analysis,
 };
+use crate::local::{file_url_to_path, is_file_url, read_audio_metadata, scan_folder};
 
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
+use diesel::SqliteConnection;
 use reqwest::header::USER_AGENT;
 
-use error_chain::error_chain;
-use std::fs::{create_dir_all, File};
+use error_chain::{bail, error_chain};
+use html2text::from_read;
+use std::fs::{self, create_dir_all, File};
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use html2text::from_read;
 use tokio::sync::Mutex;
 
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
-
 error_chain! {
+     links {
+         Db(crate::db::Error, crate::db::ErrorKind);
+     }
      foreign_links {
          Io(std::io::Error);
          HttpRequest(reqwest::Error);
+         Feed(rss::Error);
      }
 }
 
 pub enum IoEvent {
     GetPodEpisodes(Pod),
     GetPodUpdates(Pod),
-    DownloadEpisodeAudio(Episode, f32),
+    DownloadEpisodeAudio(Episode, f32, bool),
+    DownloadEpisode(Episode),
+    AnalyseEpisode(Episode),
 }
 
 pub struct Network<'a> {
     pub app: &'a Arc<Mutex<App>>,
+    conn: SqliteConnection,
+    database_url: String,
+    data_dir: PathBuf,
 }
 
 impl<'a> Network<'a> {
-    pub fn new(app: &'a Arc<Mutex<App>>) -> Network {
-        Network { app }
+    pub fn new(
+        app: &'a Arc<Mutex<App>>,
+        conn: SqliteConnection,
+        database_url: String,
+        data_dir: PathBuf,
+    ) -> Network<'a> {
+        Network {
+            app,
+            conn,
+            database_url,
+            data_dir,
+        }
     }
 
     pub async fn handle_network_event(&mut self, io_event: IoEvent) {
-        match io_event {
-            IoEvent::GetPodEpisodes(pod) => {
-                self.download_pod_and_episodes(pod).await;
-            }
-            IoEvent::GetPodUpdates(pod) => {
-                self.download_pod_updates(pod).await;
+        let result = match io_event {
+            IoEvent::GetPodEpisodes(pod) => self.download_pod_and_episodes(pod).await,
+            IoEvent::GetPodUpdates(pod) => self.download_pod_updates(pod).await,
+            IoEvent::DownloadEpisodeAudio(episode, timestamp, skip_intro) => {
+                self.download_episode_audio(episode, timestamp, skip_intro)
+                    .await
             }
-            IoEvent::DownloadEpisodeAudio(episode, timestamp) => {
-                let _ = self.download_episode_audio(episode, timestamp).await;
+            IoEvent::DownloadEpisode(episode) => self.download_episode(episode).await,
+            IoEvent::AnalyseEpisode(episode) => {
+                // Decoding a whole file takes a while, so it runs beside the queue
+                // with its own db handle and leaves the indicators alone
+                let app = Arc::clone(self.app);
+                let database_url = self.database_url.clone();
+                tokio::spawn(async move {
+                    let result = analyse_audio(&app, &database_url, &episode).await;
+                    app.lock().await.report(result);
+                });
+                return;
             }
-        }
+        };
+        self.finish_event(result).await;
+    }
+
+    /// Reports an event that panicked and clears the indicators it left on
+    pub async fn report_panic(&mut self, message: &str) {
+        let result = Err(Error::from(format!("network event panicked: {}", message)));
+        self.finish_event(result).await;
+    }
+
+    async fn finish_event(&mut self, result: Result<()>) {
         let mut app = self.app.lock().await;
+        app.report(result);
         app.is_loading = false;
         app.is_downloading = false;
+        app.is_refreshing = false;
     }
 
-    async fn download_pod_and_episodes(&mut self, pod: Pod) {
-        let client = reqwest::Client::new();
-        let res = client
-            .get(&pod.url)
-            .header(USER_AGENT, "fred_podplayer_tui")
-            .send()
-            .await;
-        match res {
-            Ok(result) => match result.bytes().await {
-                Ok(result) => {
-                    let channel = rss::Channel::read_from(&result[..]);
-                    let mut conn = establish_connection();
-                    match channel {
-                        Ok(chan) => {
-                            for item in chan.items().iter() {
-                                let dt = item.pub_date().unwrap();
-                                let dt2 = DateTime::parse_from_rfc2822(dt).unwrap();
-                                let item_description = from_read(item.description().unwrap().as_bytes(), 80);
-                                create_episode(
-                                    &mut conn,
-                                    item.guid().unwrap().value(),
-                                    pod.id,
-                                    item.title().unwrap(),
-                                    item.link().unwrap_or(""),
-                                    item.enclosure().unwrap().url(),
-                                    item_description.as_str(),
-                                    dt2.timestamp() as i32,
-                                    false,
-                                );
-                            }
-                            mark_pod_as_downloaded(&mut conn, pod.id);
-                        }
-                        Err(err) => panic!("failed to download episodes: {}", err),
-                    }
-                    let mut app = self.app.lock().await;
-                    app.set_active_pod(pod.id);
-                }
-                Err(_e) => {}
-            },
-            Err(_e) => {}
+    async fn download_pod_and_episodes(&mut self, pod: Pod) -> Result<()> {
+        self.store_episodes(&pod, false).await?;
+        mark_pod_as_downloaded(&mut self.conn, pod.id)?;
+        let mut app = self.app.lock().await;
+        app.reload_pods();
+        app.set_active_pod(pod.id);
+        Ok(())
+    }
+
+    async fn download_pod_updates(&mut self, pod: Pod) -> Result<()> {
+        // Only episodes first seen on a refresh are new, a first download brings in the back catalogue
+        self.store_episodes(&pod, true).await?;
+        let mut app = self.app.lock().await;
+        app.reload_pods();
+        app.set_active_pod(pod.id);
+        Ok(())
+    }
+
+    /// Reads the episodes of a feed or folder into the db
+    async fn store_episodes(&mut self, pod: &Pod, is_new: bool) -> Result<()> {
+        if let Some(dir) = self.local_folder(pod) {
+            let local_episodes = scan_folder(&dir)?;
+            let new_episodes: Vec<NewEpisode> = local_episodes
+                .iter()
+                .map(|local_episode| local_episode.as_new_episode(pod.id, is_new))
+                .collect();
+            create_episodes(&mut self.conn, &new_episodes)?;
+        } else {
+            let result = self.fetch_feed(&pod.url).await?;
+            let chan =
+                rss::Channel::read_from(&result[..]).chain_err(|| "failed to download episodes")?;
+            self.create_episodes_from_channel(pod.id, &chan, is_new)?;
         }
+        mark_pod_as_refreshed(&mut self.conn, pod.id, Utc::now().timestamp() as i32)?;
+        Ok(())
     }
 
-    async fn download_pod_updates(&mut self, pod: Pod) {
-        let mut conn = establish_connection();
-        let existing_episodes = get_episodes_for_pod(&mut conn, pod.id);
-        let uids: Vec<String> = existing_episodes.iter().map(|ep| ep.uid.clone()).collect();
+    /// Returns the directory of a folder podcast, `None` for regular feeds
+    fn local_folder(&self, pod: &Pod) -> Option<PathBuf> {
+        if !is_file_url(&pod.url) {
+            return None;
+        }
+        file_url_to_path(&pod.url).filter(|path| path.is_dir())
+    }
 
+    /// Reads the raw feed from a `file://` URL or over http
+    async fn fetch_feed(&self, url: &str) -> Result<Vec<u8>> {
+        if is_file_url(url) {
+            let path = file_url_to_path(url)
+                .ok_or_else(|| Error::from(format!("invalid file url {}", url)))?;
+            return Ok(fs::read(path)?);
+        }
         let client = reqwest::Client::new();
-        let res = client
-            .get(&pod.url)
+        let result = client
+            .get(url)
             .header(USER_AGENT, "fred_podplayer_tui")
             .send()
-            .await;
-        match res {
-            Ok(result) => match result.bytes().await {
-                Ok(result) => {
-                    let channel = rss::Channel::read_from(&result[..]);
-                    let mut conn = establish_connection();
-                    match channel {
-                        Ok(chan) => {
-                            for item in chan.items().iter() {
-                                if !uids.contains(&item.guid().unwrap().value().to_string()) {
-                                    let dt = item.pub_date().unwrap();
-                                    let dt2 = DateTime::parse_from_rfc2822(dt).unwrap();
-                                    let item_description = from_read(item.description().unwrap().as_bytes(), 80);
-                                    create_episode(
-                                        &mut conn,
-                                        item.guid().unwrap().value(),
-                                        pod.id,
-                                        item.title().unwrap(),
-                                        item.link().unwrap_or(""),
-                                        item.enclosure().unwrap().url(),
-                                        item_description.as_str(),
-                                        dt2.timestamp() as i32,
-                                        false,
-                                    );
-                                }
-                            }
-                        }
-                        Err(err) => panic!("failed to download episodes: {}", err),
-                    }
-                    let mut app = self.app.lock().await;
-                    app.set_active_pod(pod.id);
-                    app.is_refreshing = false;
+            .await?;
+        Ok(result.bytes().await?.to_vec())
+    }
+
+    /// Stores every item of the feed, refreshing the metadata of items already known by uid
+    fn create_episodes_from_channel(
+        &mut self,
+        pod_id: i32,
+        chan: &rss::Channel,
+        is_new: bool,
+    ) -> Result<()> {
+        // Items without a title, audio or a readable date can't be listed, so they are skipped
+        let items: Vec<(&rss::Item, &str, &str, i64, String)> = chan
+            .items()
+            .iter()
+            .filter_map(|item| {
+                let title = item.title()?;
+                let audio_url = item.enclosure()?.url();
+                let pub_date = DateTime::parse_from_rfc2822(item.pub_date()?).ok()?;
+                let description = from_read(item.description().unwrap_or("").as_bytes(), 80);
+                Some((item, title, audio_url, pub_date.timestamp(), description))
+            })
+            .collect();
+        let new_episodes: Vec<NewEpisode> = items
+            .iter()
+            .map(|(item, title, audio_url, pub_timestamp, description)| {
+                let itunes = item.itunes_ext();
+                NewEpisode {
+                    // Feeds without guids still give every episode its own audio url
+                    uid: item.guid().map_or(*audio_url, |guid| guid.value()),
+                    pod_id,
+                    title,
+                    url: item.link().unwrap_or(""),
+                    audio_url,
+                    description: description.as_str(),
+                    audio_filepath: None,
+                    downloaded: false,
+                    played: false,
+                    timestamp: 0.0,
+                    pub_timestamp: *pub_timestamp as i32,
+                    duration: None,
+                    season: itunes
+                        .and_then(|ext| ext.season())
+                        .and_then(|s| s.parse().ok()),
+                    episode_number: itunes
+                        .and_then(|ext| ext.episode())
+                        .and_then(|e| e.parse().ok()),
+                    is_new,
                 }
-                Err(_e) => {}
-            },
-            Err(_e) => {}
-        }
+            })
+            .collect();
+        create_episodes(&mut self.conn, &new_episodes)?;
+        Ok(())
     }
 
-    async fn download_episode_audio(&mut self, episode: Episode, timestamp: f32) -> Result<()> {
-        let result = reqwest::get(&episode.audio_url).await?;
-        let filename;
-        create_dir_all("./data")?;
-        let mut dest = {
-            let fname = result
-                .url()
-                .path_segments()
-                .and_then(|segments| segments.last())
-                .and_then(|name| if name.is_empty() { None } else { Some(name) })
-                .unwrap_or("tmp.bin");
-            let fname = format!("./data/{}", fname);
-            filename = String::from(fname.as_str());
-            File::create(fname)?
-        };
-        let content = result.bytes().await?;
-        dest.write_all(&content)?;
-        let duration = self.read_metadata_from_file(&filename);
-        let mut conn = establish_connection();
-        let updated_ep =
-            mark_episode_as_downloaded(&mut conn, &episode, &filename, duration as i32);
+    async fn download_episode_audio(
+        &mut self,
+        episode: Episode,
+        timestamp: f32,
+        skip_intro: bool,
+    ) -> Result<()> {
+        let updated_ep = self.fetch_episode_audio(&episode).await?;
         let mut app = self.app.lock().await;
-        app.play_episode(updated_ep, timestamp);
+        app.play_episode(updated_ep, timestamp, skip_intro);
         Ok(())
     }
 
-    fn read_metadata_from_file(&mut self, filepath: &String) -> u64 {
-        let mut hint = Hint::new();
-        let source = {
-            let path = Path::new(filepath);
+    /// Downloads the audio without starting playback
+    async fn download_episode(&mut self, episode: Episode) -> Result<()> {
+        let updated_ep = self.fetch_episode_audio(&episode).await?;
+        let mut app = self.app.lock().await;
+        app.update_episode_in_lists(&updated_ep);
+        app.queue_analysis(&updated_ep);
+        Ok(())
+    }
 
-            if let Some(extension) = path.extension() {
-                if let Some(extension_str) = extension.to_str() {
-                    hint.with_extension(extension_str);
-                }
-            }
+    async fn fetch_episode_audio(&mut self, episode: &Episode) -> Result<Episode> {
+        let filename;
+        if is_file_url(&episode.audio_url) {
+            // Local enclosures are played in place instead of being copied into the data dir
+            let path = file_url_to_path(&episode.audio_url)
+                .ok_or_else(|| Error::from(format!("invalid file url {}", episode.audio_url)))?;
+            fs::metadata(&path)?;
+            filename = path.to_string_lossy().into_owned();
+        } else {
+            let result = reqwest::get(&episode.audio_url).await?;
+            create_dir_all(&self.data_dir)?;
+            let mut dest = {
+                let fname = result
+                    .url()
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .and_then(|name| if name.is_empty() { None } else { Some(name) })
+                    .unwrap_or("tmp.bin");
+                let fname = self.data_dir.join(fname);
+                filename = fname.to_string_lossy().into_owned();
+                File::create(fname)?
+            };
+            let content = result.bytes().await?;
+            dest.write_all(&content)?;
+        }
+        let duration = self.read_metadata_from_file(&filename)?;
+        Ok(mark_episode_as_downloaded(
+            &mut self.conn,
+            episode,
+            &filename,
+            duration as i32,
+        )?)
+    }
 
-            Box::new(File::open(path).unwrap())
-        };
-        let mss = MediaSourceStream::new(source, Default::default());
-        let metadata_opts: MetadataOptions = Default::default();
-        let format_opts = FormatOptions {
-            enable_gapless: false,
-            ..Default::default()
-        };
-        match symphonia::default::get_probe().format(&hint, mss, &format_opts, &metadata_opts) {
-            Ok(probed) => {
-                let params = &probed.format.default_track().unwrap().codec_params;
-
-                if let Some(n_frames) = params.n_frames {
-                    if let Some(tb) = params.time_base {
-                        let time = tb.calc_time(n_frames);
-                        return time.seconds;
-                    } else {
-                        return 0;
-                    }
-                } else {
-                    return 0;
-                }
-            }
-            Err(_) => panic!("could not probe audio for metadata"),
+    fn read_metadata_from_file(&mut self, filepath: &String) -> Result<u64> {
+        match read_audio_metadata(Path::new(filepath)) {
+            Some(metadata) => Ok(metadata.duration),
+            None => bail!("could not probe audio for metadata"),
         }
     }
 }
+
+/// Measures the loudness of fresh audio so playback can even it out, and finds
+/// the long silences silence skipping jumps over.
+/// Runs after playback started, the gain fades in once it is known.
+async fn analyse_audio(app: &Mutex<App>, database_url: &str, episode: &Episode) -> Result<()> {
+    let path = match &episode.audio_filepath {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(()),
+    };
+    let analysis = tokio::task::spawn_blocking(move || analyse(&path))
+        .await
+        .chain_err(|| "audio analysis failed")?;
+    // Audio that can't be decoded is stored without results, so it isn't retried on every play
+    let (loudness_gain, silences) = match analysis {
+        Some(analysis) => (analysis.loudness_gain, analysis.silences),
+        None => (None, Vec::new()),
+    };
+    let silences = serde_json::to_string(&silences).chain_err(|| "failed to serialize silences")?;
+    let mut conn = establish_connection(database_url)?;
+    let updated_ep = set_audio_analysis(&mut conn, episode.id, loudness_gain, &silences)?;
+    app.lock().await.audio_analysed(&updated_ep);
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use kira::clock::clock_info::ClockInfoProvider;
+use kira::dsp::Frame;
+use kira::modulator::value_provider::ModulatorValueProvider;
+use kira::track::effect::{Effect, EffectBuilder};
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Length of the crossfaded grains, short enough to not smear syllables
+const WINDOW_SECS: f32 = 0.05;
+
+/// Adds a [`PitchShift`] to a mixer track
+pub struct PitchShiftBuilder;
+
+/// Changes the pitch of a running [`PitchShift`] from outside the audio thread
+pub struct PitchShiftHandle {
+    factor: Arc<AtomicU32>,
+}
+
+impl PitchShiftHandle {
+    /// 1.0 leaves the pitch alone, 0.5 is an octave down
+    pub fn set_factor(&self, factor: f32) {
+        self.factor.store(factor.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl EffectBuilder for PitchShiftBuilder {
+    type Handle = PitchShiftHandle;
+
+    fn build(self) -> (Box<dyn Effect>, PitchShiftHandle) {
+        let factor = Arc::new(AtomicU32::new(1.0f32.to_bits()));
+        let effect = PitchShift {
+            factor: factor.clone(),
+            current_factor: 1.0,
+            buffer: Vec::new(),
+            write: 0,
+            window: 0.0,
+            phase: 0.0,
+        };
+        (Box::new(effect), PitchShiftHandle { factor })
+    }
+}
+
+/// Delay line pitch shifter. Two read heads drift through a short delay line at the
+/// pitch factor, half a window apart, and each fades out before it wraps around.
+/// Speech survives this well, which is all that gets sped up here.
+struct PitchShift {
+    factor: Arc<AtomicU32>,
+    current_factor: f32,
+    buffer: Vec<Frame>,
+    write: usize,
+    window: f32,
+    phase: f32,
+}
+
+impl PitchShift {
+    /// Linearly interpolated frame `delay` samples before the newest one
+    fn read(&self, delay: f32) -> Frame {
+        let len = self.buffer.len();
+        let position = (self.write as f32 - delay).rem_euclid(len as f32);
+        let index = position as usize;
+        let fraction = position - index as f32;
+        let current = self.buffer[index % len];
+        let next = self.buffer[(index + 1) % len];
+        current + (next - current) * fraction
+    }
+
+    fn shift(&mut self, input: Frame) -> Frame {
+        if self.buffer.is_empty() {
+            return input;
+        }
+        self.buffer[self.write] = input;
+        let output = if (self.current_factor - 1.0).abs() < 0.001 {
+            input
+        } else {
+            let mut output = Frame::ZERO;
+            for offset in [0.0, 0.5] {
+                let phase = (self.phase + offset).fract();
+                // sin² windows half a period apart always add up to one
+                let gain = (PI * phase).sin().powi(2);
+                // One sample of headroom so interpolation never reads past the newest frame
+                output += self.read(phase * self.window + 1.0) * gain;
+            }
+            output
+        };
+        self.phase = (self.phase + (1.0 - self.current_factor) / self.window).rem_euclid(1.0);
+        self.write = (self.write + 1) % self.buffer.len();
+        output
+    }
+}
+
+impl Effect for PitchShift {
+    fn init(&mut self, sample_rate: u32) {
+        self.on_change_sample_rate(sample_rate);
+    }
+
+    fn on_change_sample_rate(&mut self, sample_rate: u32) {
+        self.window = (WINDOW_SECS * sample_rate as f32).max(2.0);
+        self.buffer = vec![Frame::ZERO; self.window as usize + 3];
+        self.write = 0;
+        self.phase = 0.0;
+    }
+
+    fn on_start_processing(&mut self) {
+        self.current_factor = f32::from_bits(self.factor.load(Ordering::Relaxed));
+    }
+
+    fn process(
+        &mut self,
+        input: Frame,
+        _dt: f64,
+        _clock_info_provider: &ClockInfoProvider,
+        _modulator_value_provider: &ModulatorValueProvider,
+    ) -> Frame {
+        self.shift(input)
+    }
+}
@@ -3,8 +3,10 @@ use kira::{
     sound::{
         streaming::{StreamingSoundData, StreamingSoundSettings},
         SoundData,
+        PlaybackRate,
         PlaybackState,
     },
+    track::{TrackBuilder, TrackHandle},
     tween::Tween,
     Volume,
 };
@@ -12,40 +14,134 @@ use kira::sound::FromFileError;
 
 use symphonia::core::units::Time;
 
+use std::time::Duration;
+
+use crate::analysis::Silence;
 use crate::db::models::Episode;
+use crate::pitch_shift::{PitchShiftBuilder, PitchShiftHandle};
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+pub const DEFAULT_VOLUME: u8 = 75;
+/// Silence left in place around a skip, so speech doesn't start abruptly
+const SILENCE_MARGIN_SECS: f64 = 0.3;
 
 pub struct Player {
     manager: AudioManager,
     pub selected_track: Option<Episode>,
     handler: Option<<StreamingSoundData<FromFileError> as SoundData>::Handle>,
+    end_reported: bool,
+    // Sounds play through this track so its pitch shifter can undo the
+    // pitch change that comes with a faster or slower playback rate
+    track: TrackHandle,
+    pitch_shift: PitchShiftHandle,
+    speed: f32,
+    // In percent, applied on the track so it carries over to the next episode
+    volume: u8,
+    muted: bool,
+    normalize_loudness: bool,
+    skip_silence: bool,
+    // Long silences of the selected track, from its audio analysis
+    silences: Vec<Silence>,
 }
 
 impl Player {
     pub fn new() -> Player {
-        let manager = AudioManager::<CpalBackend>::new(AudioManagerSettings::default()).unwrap();
-        Player {
+        let mut manager =
+            AudioManager::<CpalBackend>::new(AudioManagerSettings::default()).unwrap();
+        let mut track_builder = TrackBuilder::new();
+        let pitch_shift = track_builder.add_effect(PitchShiftBuilder);
+        let track = manager.add_sub_track(track_builder).unwrap();
+        let mut player = Player {
             manager,
             selected_track: None,
             handler: None,
-        }
+            end_reported: false,
+            track,
+            pitch_shift,
+            speed: 1.0,
+            volume: DEFAULT_VOLUME,
+            muted: false,
+            normalize_loudness: true,
+            skip_silence: false,
+            silences: Vec::new(),
+        };
+        player.apply_volume();
+        player
     }
 
     pub fn play(&mut self) {
+        let gain = self.loudness_gain();
         if let Some(track) = &mut self.selected_track {
             if let Some(handler) = &mut self.handler {
                 let _ = handler.stop(kira::tween::Tween::default());
             }
 
-            let sound = StreamingSoundData::from_file(
-                track.audio_filepath.as_ref().unwrap(),
-                StreamingSoundSettings::default(),
-            )
-            .unwrap();
+            let settings = StreamingSoundSettings::new()
+                .output_destination(&self.track)
+                .playback_rate(PlaybackRate::Factor(self.speed as f64))
+                .volume(Volume::Decibels(gain));
+            let sound =
+                StreamingSoundData::from_file(track.audio_filepath.as_ref().unwrap(), settings)
+                    .unwrap();
             self.handler = Some(self.manager.play(sound).unwrap());
-            if let Some(handler) = &mut self.handler {
-                let _ = handler.set_volume(Volume::Amplitude(0.75), Tween::default());
-            }
+            self.end_reported = false;
+        }
+        self.load_silences();
+    }
+
+    /// Picks up the silences of the selected track, e.g. once its analysis finished
+    pub fn load_silences(&mut self) {
+        self.silences = self
+            .selected_track
+            .as_ref()
+            .and_then(|track| track.silences.as_deref())
+            .and_then(|json| serde_json::from_str(json).ok())
+            .unwrap_or_default();
+    }
+
+    pub fn skips_silence(&self) -> bool {
+        self.skip_silence
+    }
+
+    pub fn set_skip_silence(&mut self, enabled: bool) {
+        self.skip_silence = enabled;
+    }
+
+    /// Jumps to the end of a long silence when playback is inside one.
+    /// Returns the listening time saved in seconds.
+    pub fn skip_silence(&mut self) -> Option<f32> {
+        if !self.skip_silence {
+            return None;
+        }
+        let handler = self.handler.as_mut()?;
+        if handler.state() != PlaybackState::Playing {
+            return None;
         }
+        let position = handler.position();
+        let silence = self.silences.iter().find(|silence| {
+            position >= silence.start as f64 + SILENCE_MARGIN_SECS
+                && position < silence.end as f64 - SILENCE_MARGIN_SECS
+        })?;
+        let target = silence.end as f64 - SILENCE_MARGIN_SECS;
+        handler.seek_to(target).ok()?;
+        Some(((target - position) / self.speed as f64) as f32)
+    }
+
+    /// Ends the current track early, it then counts as finished like one that played to its end
+    pub fn finish(&mut self) {
+        if let Some(handler) = &mut self.handler {
+            let _ = handler.stop(Tween::default());
+        }
+    }
+
+    /// Stops playback and forgets the track, for when its episode is gone
+    pub fn unload(&mut self) {
+        if let Some(mut handler) = self.handler.take() {
+            let _ = handler.stop(Tween::default());
+        }
+        self.selected_track = None;
+        self.silences.clear();
     }
 
     pub fn toggle_playback(&mut self) {
@@ -62,34 +158,155 @@ impl Player {
 
     pub fn get_playback_state(&mut self) -> PlaybackState {
         if let Some(handler) = &mut self.handler {
-            return handler.state();
+            handler.state()
         } else {
-            return PlaybackState::Stopped;
+            PlaybackState::Stopped
         }
     }
 
-    pub fn get_current_timestamp(&mut self) -> f32 {
-        match &self.handler {
-            Some(handler) => return handler.position().clone() as f32,
-            None => return 0.0,
+    /// True once after the current track played through to its end.
+    /// Kira stops a streaming sound by itself when it runs out of audio.
+    pub fn has_finished(&mut self) -> bool {
+        if self.end_reported || self.get_playback_state() != PlaybackState::Stopped {
+            return false;
+        }
+        self.end_reported = self.handler.is_some();
+        self.end_reported
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Changes the playback rate, keeping voices at their natural pitch.
+    /// Returns the speed actually set, rounded to 0.05 and kept within limits.
+    pub fn set_speed(&mut self, speed: f32) -> f32 {
+        self.speed = ((speed * 20.0).round() / 20.0).clamp(MIN_SPEED, MAX_SPEED);
+        self.pitch_shift.set_factor(1.0 / self.speed);
+        if let Some(handler) = &mut self.handler {
+            let _ = handler
+                .set_playback_rate(PlaybackRate::Factor(self.speed as f64), Tween::default());
         }
+        self.speed
     }
 
-    pub fn jump_forward_10s(&mut self) {
+    pub fn normalizes_loudness(&self) -> bool {
+        self.normalize_loudness
+    }
+
+    pub fn set_normalize_loudness(&mut self, enabled: bool) {
+        self.normalize_loudness = enabled;
+        self.apply_loudness_gain();
+    }
+
+    /// Gain of the selected track in dB, zero when unknown or normalization is off
+    fn loudness_gain(&self) -> f64 {
+        match &self.selected_track {
+            Some(track) if self.normalize_loudness => track.loudness_gain.unwrap_or(0.0) as f64,
+            _ => 0.0,
+        }
+    }
+
+    /// Brings the playing sound to the gain of the selected track, e.g. once it was measured
+    pub fn apply_loudness_gain(&mut self) {
+        let gain = self.loudness_gain();
         if let Some(handler) = &mut self.handler {
-            handler.seek_by(100.0).unwrap();
+            let tween = Tween {
+                duration: Duration::from_millis(500),
+                ..Default::default()
+            };
+            let _ = handler.set_volume(Volume::Decibels(gain), tween);
         }
     }
 
-    pub fn jump_backward_10s(&mut self) {
+    /// Fades the playing sound out over `duration`, e.g. before the sleep timer
+    /// pauses it. [`Player::apply_loudness_gain`] brings it back.
+    pub fn fade_out(&mut self, duration: Duration) {
         if let Some(handler) = &mut self.handler {
-            handler.seek_by(-10.0).unwrap();
+            let tween = Tween {
+                duration,
+                ..Default::default()
+            };
+            let _ = handler.set_volume(Volume::Amplitude(0.0), tween);
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Sets the volume in percent, unmuting. Returns the volume actually set.
+    pub fn set_volume(&mut self, volume: i32) -> u8 {
+        self.volume = volume.clamp(0, 100) as u8;
+        self.muted = false;
+        self.apply_volume();
+        self.volume
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+        self.apply_volume();
+    }
+
+    fn apply_volume(&mut self) {
+        let amplitude = if self.muted {
+            0.0
+        } else {
+            self.volume as f64 / 100.0
+        };
+        // A short fade keeps volume steps from clicking
+        let tween = Tween {
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let _ = self.track.set_volume(Volume::Amplitude(amplitude), tween);
+    }
+
+    pub fn get_current_timestamp(&mut self) -> f32 {
+        match &self.handler {
+            Some(handler) => handler.position() as f32,
+            None => 0.0,
         }
     }
 
+    /// Seeks `secs` ahead, or back when negative
+    pub fn skip(&mut self, secs: f64) {
+        if let Some(handler) = &self.handler {
+            let position = handler.position();
+            self.seek((position + secs) as f32);
+        }
+    }
+
+    /// Seeks to `ts` seconds, kept between the start and the end of the track
     pub fn seek(&mut self, ts: f32) {
+        let end = self.duration();
         if let Some(handler) = &mut self.handler {
-            handler.seek_to(ts as f64).unwrap();
+            let mut target = (ts as f64).max(0.0);
+            if let Some(end) = end {
+                target = target.min(end as f64);
+            }
+            // Only fails when kira's command queue is full, the next key press retries
+            let _ = handler.seek_to(target);
+        }
+    }
+
+    /// Length of the selected track in seconds, when the feed or file told us
+    pub fn duration(&self) -> Option<f32> {
+        self.selected_track
+            .as_ref()
+            .and_then(|track| track.duration)
+            .filter(|&duration| duration > 0)
+            .map(|duration| duration as f32)
+    }
+
+    /// Seeks to `percent` of the track, e.g. 50 for the middle
+    pub fn seek_to_percent(&mut self, percent: u32) {
+        if let Some(duration) = self.duration() {
+            self.seek(duration * percent as f32 / 100.0);
         }
     }
 
@@ -99,9 +316,12 @@ impl Player {
             let cur_dur = self.fmt_time(pos.seconds);
             let dur = self.selected_track.as_ref().unwrap().duration.unwrap();
             let tot_dur = self.fmt_time(dur as u64);
-            return format!("{} / {}", cur_dur, tot_dur);
+            // Remaining is wall clock time, so it shrinks with the speed
+            let remaining = (dur as f64 - pos.seconds as f64).max(0.0) / self.speed as f64;
+            let remaining = self.fmt_time(remaining as u64);
+            return format!("{} / {} (-{})", cur_dur, tot_dur, remaining);
         }
-        return String::from("");
+        String::from("")
     }
 
     fn fmt_time(&mut self, secs: u64) -> String {
@@ -109,6 +329,154 @@ impl Player {
         let mins = (secs % (60 * 60)) / 60;
         let secs = (secs % 60) as u32;
 
-        return format!("{}:{:0>2}:{:0>2}", hours, mins, secs);
+        format!("{}:{:0>2}:{:0>2}", hours, mins, secs)
+    }
+}
+
+/// Parses `hh:mm:ss` or `mm:ss` into seconds
+pub fn parse_time(text: &str) -> Option<u32> {
+    let parts = text
+        .trim()
+        .split(':')
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    match parts[..] {
+        [mins, secs] if secs < 60 => mins.checked_mul(60)?.checked_add(secs),
+        [hours, mins, secs] if mins < 60 && secs < 60 => {
+            hours.checked_mul(3600)?.checked_add(mins * 60 + secs)
+        }
+        _ => None,
+    }
+}
+
+/// A time mentioned in show notes, with the line it appears on
+#[derive(Clone, Debug)]
+pub struct Timestamp {
+    pub secs: u32,
+    pub line: String,
+}
+
+/// Finds `hh:mm:ss` and `mm:ss` times in show notes, in the order they appear
+pub fn find_timestamps(text: &str) -> Vec<Timestamp> {
+    let mut timestamps = Vec::new();
+    for line in text.lines() {
+        // Times are often wrapped like "(12:34)" or "<p>12:34</p>", or followed
+        // by a dash or colon
+        let words = line
+            .split(|c: char| !c.is_ascii_digit() && c != ':')
+            .map(|word| word.trim_matches(':'));
+        for word in words {
+            if let Some(secs) = parse_time(word) {
+                timestamps.push(Timestamp {
+                    secs,
+                    line: line.trim().to_string(),
+                });
+            }
+        }
+    }
+    timestamps
+}
+
+/// Parses a go to position, `hh:mm:ss`, `mm:ss` or a percentage like `25%`, into
+/// seconds. Percentages need the track's `duration`.
+pub fn parse_position(text: &str, duration: Option<f32>) -> Option<f32> {
+    match text.trim().strip_suffix('%') {
+        Some(percent) => {
+            let percent = percent.trim().parse::<f32>().ok()?;
+            if !(0.0..=100.0).contains(&percent) {
+                return None;
+            }
+            Some(duration? * percent / 100.0)
+        }
+        None => parse_time(text).map(|secs| secs as f32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_minutes_and_hours() {
+        assert_eq!(parse_time("0:00"), Some(0));
+        assert_eq!(parse_time("12:34"), Some(754));
+        assert_eq!(parse_time(" 1:02:03 "), Some(3723));
+        assert_eq!(parse_time("90:00"), Some(5400));
+    }
+
+    #[test]
+    fn rejects_out_of_range_parts() {
+        assert_eq!(parse_time("12:60"), None);
+        assert_eq!(parse_time("1:60:00"), None);
+        assert_eq!(parse_time("1:00:60"), None);
+        assert_eq!(parse_time("1234"), None);
+        assert_eq!(parse_time("1:2:3:4"), None);
+        assert_eq!(parse_time("12:"), None);
+        assert_eq!(parse_time("-1:00"), None);
+    }
+
+    #[test]
+    fn rejects_times_that_overflow() {
+        assert_eq!(parse_time("71582788:59"), None);
+        assert_eq!(parse_time("1193046:28:16"), None);
+        assert_eq!(parse_time("4294967295:00"), None);
+        assert_eq!(parse_time("71582788:15"), Some(4294967295));
+    }
+
+    fn secs(timestamps: &[Timestamp]) -> Vec<u32> {
+        timestamps.iter().map(|timestamp| timestamp.secs).collect()
+    }
+
+    #[test]
+    fn finds_timestamps_in_order() {
+        let notes = "Intro\n00:00 Welcome\n12:34 News\n1:02:03 Listener mail";
+        let timestamps = find_timestamps(notes);
+        assert_eq!(secs(&timestamps), vec![0, 754, 3723]);
+        assert_eq!(timestamps[1].line, "12:34 News");
+    }
+
+    #[test]
+    fn finds_wrapped_timestamps() {
+        let notes = "(12:34) News\n[1:02:03] Mail\nOutro 45:00:\n  50:00 - Bonus  ";
+        let timestamps = find_timestamps(notes);
+        assert_eq!(secs(&timestamps), vec![754, 3723, 2700, 3000]);
+        assert_eq!(timestamps[3].line, "50:00 - Bonus");
+    }
+
+    #[test]
+    fn finds_timestamps_in_html() {
+        let notes = "<p>(00:45) Intro</p>\n<ul><li>12:34</li><li><b>45:00</b> Outro</li></ul>";
+        assert_eq!(secs(&find_timestamps(notes)), vec![45, 754, 2700]);
+    }
+
+    #[test]
+    fn skips_numbers_that_are_not_times() {
+        let notes = "Recorded 2024-10-18, episode 12, rated 4/5\nScore 12:75 and 1:2:3:4";
+        assert!(find_timestamps(notes).is_empty());
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(parse_position("1:30", None), Some(90.0));
+        assert_eq!(parse_position("1:30", Some(60.0)), Some(90.0));
+        assert_eq!(parse_position("nope", Some(60.0)), None);
+    }
+
+    #[test]
+    fn parses_percentages_of_the_duration() {
+        assert_eq!(parse_position("25%", Some(200.0)), Some(50.0));
+        assert_eq!(parse_position(" 50 % ", Some(200.0)), Some(100.0));
+        assert_eq!(parse_position("0%", Some(200.0)), Some(0.0));
+        assert_eq!(parse_position("100%", Some(200.0)), Some(200.0));
+        assert_eq!(parse_position("12.5%", Some(80.0)), Some(10.0));
+    }
+
+    #[test]
+    fn rejects_bad_percentages() {
+        assert_eq!(parse_position("25%", None), None);
+        assert_eq!(parse_position("101%", Some(200.0)), None);
+        assert_eq!(parse_position("-1%", Some(200.0)), None);
+        assert_eq!(parse_position("%", Some(200.0)), None);
+        assert_eq!(parse_position("half%", Some(200.0)), None);
     }
 }
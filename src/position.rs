@@ -0,0 +1,29 @@
+use crate::db::{establish_connection, set_timestamp_on_episode};
+
+use std::sync::{Arc, Mutex};
+
+/// The last known playback position, kept outside the app lock for the panic
+/// hook and the signal handlers, which can't wait for that lock
+#[derive(Clone, Default)]
+pub struct PositionTracker {
+    position: Arc<Mutex<Option<(i32, f32)>>>,
+}
+
+impl PositionTracker {
+    pub fn update(&self, episode_id: i32, timestamp: f32) {
+        // A panic while holding the lock leaves a position that is still good to save
+        let mut position = self.position.lock().unwrap_or_else(|e| e.into_inner());
+        *position = Some((episode_id, timestamp));
+    }
+
+    /// Writes the last known position through a fresh db connection. Errors are
+    /// ignored, this runs while the app is going down.
+    pub fn save(&self, database_url: &str) {
+        let position = *self.position.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((episode_id, timestamp)) = position {
+            if let Ok(mut conn) = establish_connection(database_url) {
+                let _ = set_timestamp_on_episode(&mut conn, episode_id, timestamp);
+            }
+        }
+    }
+}